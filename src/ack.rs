@@ -0,0 +1,276 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use tick_id::TickId;
+
+use crate::sequence::TickSequence;
+use crate::steps_range::StepsRange;
+use crate::{read_octets, DecodeError, Deserialize, ParticipantSteps, Serialize, Steps};
+
+pub const RECEIVE_MASK_TICK_COUNT: usize = u64::BITS as usize;
+
+/// The receiver's view of its window: every tick before `front_tick_id` has been received,
+/// and bit `n` of `bits` is set when `front_tick_id + n` has been received.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ReceiveMask {
+    pub front_tick_id: TickId,
+    pub bits: u64,
+}
+
+impl ReceiveMask {
+    pub fn new(front_tick_id: TickId, bits: u64) -> Self {
+        Self {
+            front_tick_id,
+            bits,
+        }
+    }
+
+    pub fn is_received(&self, tick_id: TickId) -> bool {
+        let index = tick_id.wrapping_diff(self.front_tick_id);
+        if index < 0 {
+            return true;
+        }
+        index < RECEIVE_MASK_TICK_COUNT as i64 && self.bits & (1 << index) != 0
+    }
+
+    /// The oldest tick that the receiver is still waiting for. Every tick before it has been received.
+    pub fn first_missing_tick_id(&self) -> TickId {
+        self.front_tick_id.wrapping_add(self.bits.trailing_ones())
+    }
+
+    pub fn received_tick_ids(&self) -> impl Iterator<Item = TickId> + '_ {
+        (0..RECEIVE_MASK_TICK_COUNT as u32)
+            .filter(|index| self.bits & (1 << index) != 0)
+            .map(|index| self.front_tick_id.wrapping_add(index))
+    }
+}
+
+impl Serialize for ReceiveMask {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.front_tick_id.value().to_be_bytes());
+        buffer.extend_from_slice(&self.bits.to_be_bytes());
+    }
+}
+
+impl Deserialize for ReceiveMask {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let front_tick_id = TickId(u32::from_be_bytes(read_octets(bytes)?));
+        let bits = u64::from_be_bytes(read_octets(&bytes[4..])?);
+        Ok((Self::new(front_tick_id, bits), 12))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TickRange {
+    pub start_tick_id: TickId,
+    pub count: u16,
+}
+
+impl TickRange {
+    pub fn new(start_tick_id: TickId, count: u16) -> Self {
+        Self {
+            start_tick_id,
+            count,
+        }
+    }
+
+    pub fn end_tick_id(&self) -> TickId {
+        self.start_tick_id.wrapping_add(self.count as u32)
+    }
+
+    /// Splits `count` ticks from `start_tick_id` into as few ranges as the u16 count allows.
+    pub fn split(start_tick_id: TickId, count: usize) -> impl Iterator<Item = TickRange> {
+        (0..count).step_by(u16::MAX as usize).map(move |offset| {
+            TickRange::new(
+                start_tick_id.wrapping_add(offset as u32),
+                (count - offset).min(u16::MAX as usize) as u16,
+            )
+        })
+    }
+}
+
+/// Ticks that a receiver is missing, as non-overlapping ranges in ascending tick order.
+///
+/// Encoded as the start tick (u32) of the first range and the range count (u16), followed by each
+/// range as the gap (u16) from the end of the previous range and the tick count (u16). Gaps that do not fit
+/// in a u16 are bridged with ranges of zero ticks, which are skipped when decoding.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct MissingRanges {
+    pub ranges: Vec<TickRange>,
+}
+
+impl MissingRanges {
+    pub fn new(ranges: Vec<TickRange>) -> Self {
+        Self { ranges }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn tick_count(&self) -> usize {
+        self.ranges.iter().map(|range| range.count as usize).sum()
+    }
+}
+
+/// Ranges that overlap or precede the previous range are left out, and only as many ranges as fit
+/// in the u16 range count are written. The rest can be requested again later.
+impl Serialize for MissingRanges {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        let first_tick_id = self.ranges.first().map_or(TickId(0), |range| range.start_tick_id);
+        buffer.extend_from_slice(&first_tick_id.value().to_be_bytes());
+        let count_position = buffer.len();
+        buffer.extend_from_slice(&[0, 0]);
+
+        let mut encoded_count: u16 = 0;
+        let mut previous_end_tick_id = first_tick_id;
+        'ranges: for range in &self.ranges {
+            let mut gap = range.start_tick_id.wrapping_diff(previous_end_tick_id);
+            if gap < 0 {
+                continue;
+            }
+            loop {
+                if encoded_count == u16::MAX {
+                    break 'ranges;
+                }
+                encoded_count += 1;
+                if gap <= u16::MAX as i64 {
+                    break;
+                }
+                buffer.extend_from_slice(&u16::MAX.to_be_bytes());
+                buffer.extend_from_slice(&0u16.to_be_bytes());
+                gap -= u16::MAX as i64;
+            }
+            buffer.extend_from_slice(&(gap as u16).to_be_bytes());
+            buffer.extend_from_slice(&range.count.to_be_bytes());
+            previous_end_tick_id = range.end_tick_id();
+        }
+        buffer[count_position..count_position + 2].copy_from_slice(&encoded_count.to_be_bytes());
+    }
+}
+
+impl Deserialize for MissingRanges {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let first_tick_id = TickId(u32::from_be_bytes(read_octets(bytes)?));
+        let range_count = u16::from_be_bytes(read_octets(&bytes[4..])?);
+        let mut position = 6;
+        let mut previous_end_tick_id = first_tick_id;
+        let mut ranges = Vec::new();
+        for _ in 0..range_count {
+            let [gap_high, gap_low, count_high, count_low] = read_octets(&bytes[position..])?;
+            let range = TickRange::new(
+                previous_end_tick_id.wrapping_add(u16::from_be_bytes([gap_high, gap_low]) as u32),
+                u16::from_be_bytes([count_high, count_low]),
+            );
+            previous_end_tick_id = range.end_tick_id();
+            if range.count > 0 {
+                ranges.push(range);
+            }
+            position += 4;
+        }
+        Ok((Self::new(ranges), position))
+    }
+}
+
+impl<T> Steps<T> {
+    /// The stored steps for each of the `missing` ranges, ready to be resent.
+    pub fn missing_steps_ranges(&self, missing: &MissingRanges) -> Vec<StepsRange<&ParticipantSteps<T>>> {
+        missing
+            .ranges
+            .iter()
+            .map(|range| {
+                let steps_range = self.steps_range(range.start_tick_id, range.count as usize);
+                let skipped = steps_range.start_tick_id.wrapping_diff(range.start_tick_id) as usize;
+                let mut steps = steps_range.steps;
+                steps.truncate((range.count as usize).saturating_sub(skipped));
+                StepsRange::new(steps_range.start_tick_id, steps)
+            })
+            .filter(|steps_range| !steps_range.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn received_ticks() {
+        let mask = ReceiveMask::new(TickId(100), 0b1011);
+        assert!(mask.is_received(TickId(3)));
+        assert!(mask.is_received(TickId(100)));
+        assert!(mask.is_received(TickId(101)));
+        assert!(!mask.is_received(TickId(102)));
+        assert!(mask.is_received(TickId(103)));
+        assert!(!mask.is_received(TickId(164)));
+        assert_eq!(mask.first_missing_tick_id(), TickId(102));
+        assert_eq!(
+            mask.received_tick_ids().collect::<Vec<_>>(),
+            vec![TickId(100), TickId(101), TickId(103)]
+        );
+    }
+
+    #[test]
+    fn serialize_and_deserialize() {
+        let mask = ReceiveMask::new(TickId(0x01020304), 1 << 63 | 5);
+        let mut buffer = Vec::new();
+        mask.serialize(&mut buffer);
+        assert_eq!(buffer.len(), 12);
+        assert_eq!(ReceiveMask::deserialize(&buffer), Ok((mask, 12)));
+        assert!(ReceiveMask::deserialize(&buffer[..11]).is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize_missing_ranges() {
+        let missing = MissingRanges::new(vec![
+            TickRange::new(TickId(105), 2),
+            TickRange::new(TickId(108), 1),
+            TickRange::new(TickId(400), 30),
+        ]);
+        let mut buffer = Vec::new();
+        missing.serialize(&mut buffer);
+        assert_eq!(buffer.len(), 6 + 3 * 4);
+        assert_eq!(MissingRanges::deserialize(&buffer), Ok((missing.clone(), buffer.len())));
+        assert_eq!(missing.tick_count(), 33);
+
+        let mut buffer = Vec::new();
+        MissingRanges::default().serialize(&mut buffer);
+        assert_eq!(MissingRanges::deserialize(&buffer), Ok((MissingRanges::default(), 6)));
+    }
+
+    #[test]
+    fn serialize_missing_ranges_far_apart() {
+        let missing = MissingRanges::new(vec![
+            TickRange::new(TickId(10), 1),
+            TickRange::new(TickId(10 + 1 + 2 * u16::MAX as u32 + 7), 3),
+        ]);
+        let mut buffer = Vec::new();
+        missing.serialize(&mut buffer);
+        assert_eq!(buffer.len(), 6 + 4 * 4);
+        assert_eq!(MissingRanges::deserialize(&buffer), Ok((missing, buffer.len())));
+
+        let split: Vec<_> = TickRange::split(TickId(0), u16::MAX as usize + 2).collect();
+        assert_eq!(
+            split,
+            vec![TickRange::new(TickId(0), u16::MAX), TickRange::new(TickId(u16::MAX as u32), 2)]
+        );
+    }
+
+    #[test]
+    fn answer_missing_ranges_from_history() {
+        let mut steps = Steps::<u8>::new_with_initial_tick(TickId(100));
+        for _ in 100..110 {
+            steps.push(ParticipantSteps::new());
+        }
+        let missing = MissingRanges::new(vec![
+            TickRange::new(TickId(98), 4),
+            TickRange::new(TickId(105), 2),
+            TickRange::new(TickId(109), 3),
+            TickRange::new(TickId(120), 3),
+        ]);
+        let ranges = steps.missing_steps_ranges(&missing);
+        let summary: Vec<_> = ranges.iter().map(|range| (range.start_tick_id.value(), range.len())).collect();
+        assert_eq!(summary, vec![(100, 2), (105, 2), (109, 1)]);
+    }
+}
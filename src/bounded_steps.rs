@@ -0,0 +1,223 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::error::Error;
+use std::fmt;
+
+use tick_id::TickId;
+
+use crate::{ParticipantSteps, StepInfo, Steps, StepsError};
+
+/// What [`BoundedSteps`] does with a push when it already holds `capacity` steps.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OverflowPolicy {
+    /// The new step is dropped and [`StepsError::CapacityExceeded`] is returned.
+    Reject,
+    /// The oldest step is removed to make room for the new one.
+    DropOldest,
+    /// The new step is refused with [`StepsError::Backpressure`] and handed back in the [`PushError`],
+    /// so that the caller can retry it once steps have been consumed.
+    Backpressure,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct OverflowMetrics {
+    pub rejected_count: u64,
+    pub dropped_oldest_count: u64,
+    pub backpressure_count: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PushOutcome {
+    Pushed,
+    DroppedOldest(TickId),
+}
+
+/// A refused push, with the step that was not pushed.
+#[derive(Debug, PartialEq)]
+pub struct PushError<T> {
+    pub error: StepsError,
+    pub step: ParticipantSteps<T>,
+}
+
+impl<T> fmt::Display for PushError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<T: fmt::Debug> Error for PushError<T> {}
+
+/// [`Steps`] that never holds more than `capacity` steps, so a stalled or malicious peer can not grow it forever.
+pub struct BoundedSteps<T> {
+    steps: Steps<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    metrics: OverflowMetrics,
+}
+
+impl<T> BoundedSteps<T> {
+    pub fn new(initial_tick_id: TickId, capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "bounded steps must have room for at least one step");
+        Self {
+            steps: Steps::new_with_initial_tick(initial_tick_id),
+            capacity,
+            policy,
+            metrics: OverflowMetrics::default(),
+        }
+    }
+
+    pub fn push(&mut self, step: ParticipantSteps<T>) -> Result<PushOutcome, PushError<T>> {
+        let outcome = match self.make_room() {
+            Ok(outcome) => outcome,
+            Err(error) => return Err(PushError { error, step }),
+        };
+        self.steps.push(step);
+        Ok(outcome)
+    }
+
+    pub fn push_at(&mut self, tick_id: TickId, step: ParticipantSteps<T>) -> Result<PushOutcome, PushError<T>> {
+        if let Err(error) = self.steps.check_next_write(tick_id) {
+            return Err(PushError { error, step });
+        }
+        self.push(step)
+    }
+
+    pub fn pop(&mut self) -> Result<Option<StepInfo<T>>, StepsError> {
+        self.steps.pop()
+    }
+
+    pub fn pop_up_to(&mut self, tick_id: TickId) {
+        self.steps.pop_up_to(tick_id);
+    }
+
+    pub fn steps(&self) -> &Steps<T> {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.steps.len() >= self.capacity
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn metrics(&self) -> OverflowMetrics {
+        self.metrics
+    }
+
+    fn make_room(&mut self) -> Result<PushOutcome, StepsError> {
+        if !self.is_full() {
+            return Ok(PushOutcome::Pushed);
+        }
+
+        match self.policy {
+            OverflowPolicy::Reject => {
+                self.metrics.rejected_count += 1;
+                Err(StepsError::CapacityExceeded {
+                    capacity: self.capacity,
+                })
+            }
+            OverflowPolicy::Backpressure => {
+                self.metrics.backpressure_count += 1;
+                Err(StepsError::Backpressure {
+                    capacity: self.capacity,
+                })
+            }
+            OverflowPolicy::DropOldest => {
+                let dropped_tick_id = self.steps.front_tick_id().expect("full steps should have a front");
+                self.steps.pop_count(1);
+                self.metrics.dropped_oldest_count += 1;
+                Ok(PushOutcome::DroppedOldest(dropped_tick_id))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ParticipantId, Step};
+
+    use super::*;
+
+    fn forced() -> ParticipantSteps<()> {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(0), Step::Forced).unwrap();
+        participant_steps
+    }
+
+    #[test]
+    fn reject_when_full() {
+        let mut steps = BoundedSteps::new(TickId(0), 2, OverflowPolicy::Reject);
+        assert_eq!(steps.push(forced()), Ok(PushOutcome::Pushed));
+        assert_eq!(steps.push(forced()), Ok(PushOutcome::Pushed));
+        assert!(steps.is_full());
+        assert_eq!(
+            steps.push(forced()).map_err(|err| err.error),
+            Err(StepsError::CapacityExceeded { capacity: 2 })
+        );
+        assert_eq!(
+            steps.push(forced()).map_err(|err| err.error),
+            Err(StepsError::CapacityExceeded { capacity: 2 })
+        );
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps.metrics().rejected_count, 2);
+
+        steps.pop().unwrap();
+        assert_eq!(steps.push_at(TickId(2), forced()), Ok(PushOutcome::Pushed));
+        assert_eq!(steps.steps().back_tick_id(), Some(TickId(2)));
+    }
+
+    #[test]
+    fn drop_oldest_when_full() {
+        let mut steps = BoundedSteps::new(TickId(100), 3, OverflowPolicy::DropOldest);
+        for _ in 0..3 {
+            steps.push(forced()).unwrap();
+        }
+        assert_eq!(steps.push(forced()), Ok(PushOutcome::DroppedOldest(TickId(100))));
+        assert_eq!(steps.push(forced()), Ok(PushOutcome::DroppedOldest(TickId(101))));
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps.metrics().dropped_oldest_count, 2);
+        assert_eq!(steps.pop().unwrap().unwrap().tick_id, TickId(102));
+    }
+
+    #[test]
+    fn backpressure_when_full() {
+        let mut steps = BoundedSteps::new(TickId(7), 1, OverflowPolicy::Backpressure);
+        steps.push(forced()).unwrap();
+        let refused = steps.push_at(TickId(8), forced()).unwrap_err();
+        assert_eq!(refused.error, StepsError::Backpressure { capacity: 1 });
+        assert_eq!(
+            steps.push_at(TickId(9), forced()).map_err(|err| err.error),
+            Err(StepsError::TickTooFarAhead {
+                tick_id: TickId(9),
+                max_tick_id: TickId(8)
+            })
+        );
+        assert_eq!(
+            steps.metrics(),
+            OverflowMetrics {
+                backpressure_count: 1,
+                ..Default::default()
+            }
+        );
+
+        steps.pop().unwrap();
+        assert_eq!(steps.push_at(TickId(8), refused.step), Ok(PushOutcome::Pushed));
+        assert_eq!(steps.pop().unwrap().unwrap().step, forced());
+    }
+}
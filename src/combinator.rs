@@ -0,0 +1,701 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+use tick_id::TickId;
+
+use crate::pending_steps::{PendingSteps, SetOutcome};
+use crate::sequence::TickSequence;
+use crate::{ParticipantId, ParticipantSteps, Step, StepInfo, Steps, StepsError, PARTICIPANT_STEPS_MAX_COUNT};
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// How long the combinator waits for a missing participant before the tick is closed with [`Step::Forced`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Deadline {
+    /// The tick is closed once some participant has delivered a step this many ticks after it.
+    Ticks(u32),
+    /// The tick is closed once this much time has passed since the first step for it arrived.
+    Duration(Duration),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConnectionState {
+    Connected,
+    /// The participant's slot is filled with [`Step::WaitingForReconnect`] every tick.
+    Disconnected,
+    /// The participant has rejoined and contributes steps again from `resume_tick_id`.
+    /// Until then, its slot is filled with [`Step::WaitingForReconnect`].
+    Reconnecting { resume_tick_id: TickId },
+}
+
+struct Participant<T> {
+    pending_steps: PendingSteps<T>,
+    state: ConnectionState,
+    joining: bool,
+}
+
+impl<T> Participant<T> {
+    fn is_waiting_for_reconnect(&self, tick_id: TickId) -> bool {
+        match self.state {
+            ConnectionState::Connected => false,
+            ConnectionState::Disconnected => true,
+            ConnectionState::Reconnecting { resume_tick_id } => tick_id.is_before(resume_tick_id),
+        }
+    }
+}
+
+/// Combines the separately received step streams of all participants into one [`ParticipantSteps`] per tick.
+///
+/// Each participant's steps are reordered in its own [`PendingSteps`], and a tick is emitted once every
+/// participant has contributed a step for it, or when the [`Deadline`] has passed, in which case the
+/// missing participants get a [`Step::Forced`]. Disconnected participants do not hold up the other participants.
+///
+/// Roster changes are recorded in the combined steps: an added participant gets a [`Step::Joined`] in the next
+/// combined tick and a removed participant a [`Step::Left`], so a replay of the combined steps sees the same roster.
+pub struct StepCombinator<T, C = SystemClock> {
+    participants: BTreeMap<ParticipantId, Participant<T>>,
+    left_participant_ids: BTreeSet<ParticipantId>,
+    window_size: usize,
+    next_tick_id: TickId,
+    deadline: Option<Deadline>,
+    clock: C,
+    next_tick_first_received_at: Option<Instant>,
+    newest_received_tick_id: Option<TickId>,
+}
+
+impl<T> StepCombinator<T> {
+    pub fn new(window_size: usize, initial_tick_id: TickId) -> Self {
+        Self::with_clock(window_size, initial_tick_id, SystemClock)
+    }
+}
+
+impl<T, C: Clock> StepCombinator<T, C> {
+    pub fn with_clock(window_size: usize, initial_tick_id: TickId, clock: C) -> Self {
+        assert!(window_size > 0, "combinator window must have room for at least one tick");
+        Self {
+            participants: BTreeMap::new(),
+            left_participant_ids: BTreeSet::new(),
+            window_size,
+            next_tick_id: initial_tick_id,
+            deadline: None,
+            clock,
+            next_tick_first_received_at: None,
+            newest_received_tick_id: None,
+        }
+    }
+
+    /// Without a deadline, the combinator waits for every participant indefinitely.
+    pub fn set_deadline(&mut self, deadline: Option<Deadline>) {
+        self.deadline = deadline;
+    }
+
+    pub fn deadline(&self) -> Option<Deadline> {
+        self.deadline
+    }
+
+    /// Adds a participant that gets a [`Step::Joined`] in the next combined tick and is expected to contribute
+    /// a step for every tick after that. A participant that was removed can not be added again until its
+    /// [`Step::Left`] has been combined.
+    pub fn add_participant(&mut self, participant_id: ParticipantId) -> Result<(), StepsError> {
+        if self.participants.contains_key(&participant_id) || self.left_participant_ids.contains(&participant_id) {
+            return Err(StepsError::DuplicateParticipant(participant_id));
+        }
+        // Every participant, and every participant that is about to leave, takes a slot in the combined steps.
+        if self.participants.len() + self.left_participant_ids.len() >= PARTICIPANT_STEPS_MAX_COUNT {
+            return Err(StepsError::CapacityExceeded {
+                capacity: PARTICIPANT_STEPS_MAX_COUNT,
+            });
+        }
+        self.participants.insert(
+            participant_id,
+            Participant {
+                pending_steps: PendingSteps::new(self.window_size, self.next_tick_id.wrapping_add(1)),
+                state: ConnectionState::Connected,
+                joining: true,
+            },
+        );
+        Ok(())
+    }
+
+    /// The participant gets a [`Step::Left`] in the next combined tick, unless its [`Step::Joined`] has not been
+    /// combined yet, in which case it never shows up in the combined steps.
+    pub fn remove_participant(&mut self, participant_id: ParticipantId) -> Result<(), StepsError> {
+        let participant = self
+            .participants
+            .remove(&participant_id)
+            .ok_or(StepsError::UnknownParticipant(participant_id))?;
+        if !participant.joining {
+            self.left_participant_ids.insert(participant_id);
+        }
+        Ok(())
+    }
+
+    pub fn contains_participant(&self, participant_id: ParticipantId) -> bool {
+        self.participants.contains_key(&participant_id)
+    }
+
+    pub fn connection_state(&self, participant_id: ParticipantId) -> Option<ConnectionState> {
+        self.participants.get(&participant_id).map(|participant| participant.state)
+    }
+
+    /// From the next combined tick on, the participant's slot is filled with [`Step::WaitingForReconnect`]
+    /// until it reconnects.
+    pub fn disconnect(&mut self, participant_id: ParticipantId) -> Result<(), StepsError> {
+        self.participant_mut(participant_id)?.state = ConnectionState::Disconnected;
+        Ok(())
+    }
+
+    /// Resumes a disconnected participant at `resume_tick_id`, which must not be before the first tick the
+    /// participant can contribute to and must be within the window. That is the next combined tick, or the tick
+    /// after it if the participant's [`Step::Joined`] has not been combined yet. Steps for earlier ticks are
+    /// ignored as stale.
+    pub fn reconnect(&mut self, participant_id: ParticipantId, resume_tick_id: TickId) -> Result<(), StepsError> {
+        let next_tick_id = self.next_tick_id;
+        let window_size = self.window_size;
+        let participant = self.participant_mut(participant_id)?;
+        if participant.state == ConnectionState::Connected {
+            return Err(StepsError::ParticipantAlreadyConnected(participant_id));
+        }
+        let first_tick_id = if participant.joining {
+            next_tick_id.wrapping_add(1)
+        } else {
+            next_tick_id
+        };
+        let lead = resume_tick_id.wrapping_diff(first_tick_id);
+        if lead < 0 {
+            return Err(StepsError::TickTooOld {
+                tick_id: resume_tick_id,
+                min_tick_id: first_tick_id,
+            });
+        }
+        if lead >= window_size as i64 {
+            return Err(StepsError::TickTooFarAhead {
+                tick_id: resume_tick_id,
+                max_tick_id: first_tick_id.wrapping_add(window_size as u32 - 1),
+            });
+        }
+
+        participant.pending_steps = PendingSteps::new(window_size, resume_tick_id);
+        participant.state = if lead == 0 {
+            ConnectionState::Connected
+        } else {
+            ConnectionState::Reconnecting { resume_tick_id }
+        };
+        Ok(())
+    }
+
+    /// Stores the input a participant sent for `tick_id`. Only the combinator itself creates the other kinds of
+    /// [`Step`], so a participant can not forge roster changes or forced steps.
+    pub fn receive(&mut self, participant_id: ParticipantId, tick_id: TickId, input: T) -> Result<SetOutcome, StepsError> {
+        let participant = self.participant_mut(participant_id)?;
+        if participant.state == ConnectionState::Disconnected {
+            return Err(StepsError::ParticipantDisconnected(participant_id));
+        }
+        let outcome = participant.pending_steps.set(tick_id, Step::Custom(input))?;
+
+        if outcome == SetOutcome::Accepted {
+            if self
+                .newest_received_tick_id
+                .is_none_or(|newest_tick_id| tick_id.is_after(newest_tick_id))
+            {
+                self.newest_received_tick_id = Some(tick_id);
+            }
+            self.note_next_tick_received();
+        }
+
+        Ok(outcome)
+    }
+
+    /// The tick that will be combined next.
+    pub fn next_tick_id(&self) -> TickId {
+        self.next_tick_id
+    }
+
+    /// Whether every connected participant has contributed a step for the next tick.
+    /// A tick without connected participants is only ready if it has a roster change.
+    pub fn is_next_tick_ready(&self) -> bool {
+        let mut active_participants = self.active_participants().peekable();
+        let has_roster_change =
+            !self.left_participant_ids.is_empty() || self.participants.values().any(|participant| participant.joining);
+        (has_roster_change || active_participants.peek().is_some())
+            && active_participants.all(|participant| !participant.pending_steps.is_empty())
+    }
+
+    /// Whether the deadline for the next tick has passed while some participant is still missing.
+    pub fn is_next_tick_overdue(&self) -> bool {
+        if self.active_participants().next().is_none() {
+            return false;
+        }
+        match self.deadline {
+            None => false,
+            Some(Deadline::Ticks(tick_count)) => self
+                .newest_received_tick_id
+                .is_some_and(|newest_tick_id| newest_tick_id.wrapping_diff(self.next_tick_id) >= tick_count as i64),
+            Some(Deadline::Duration(duration)) => self
+                .next_tick_first_received_at
+                .is_some_and(|received_at| self.clock.now().saturating_duration_since(received_at) >= duration),
+        }
+    }
+
+    /// Combines the next tick, if every participant has contributed a step for it or the deadline has passed.
+    pub fn pop_combined(&mut self) -> Option<StepInfo<T>> {
+        if !self.is_next_tick_ready() && !self.is_next_tick_overdue() {
+            return None;
+        }
+
+        let tick_id = self.next_tick_id;
+        let next_tick_id = tick_id.wrapping_add(1);
+        let mut combined = ParticipantSteps::new();
+        for participant_id in std::mem::take(&mut self.left_participant_ids) {
+            push_combined(&mut combined, participant_id, Step::Left);
+        }
+        for (participant_id, participant) in &mut self.participants {
+            if participant.joining {
+                participant.joining = false;
+                participant.pending_steps.discard_up_to(next_tick_id);
+                push_combined(&mut combined, *participant_id, Step::Joined);
+                continue;
+            }
+            if participant.is_waiting_for_reconnect(tick_id) {
+                push_combined(&mut combined, *participant_id, Step::WaitingForReconnect);
+                continue;
+            }
+            if let ConnectionState::Reconnecting { .. } = participant.state {
+                participant.state = ConnectionState::Connected;
+            }
+
+            let step = match participant.pending_steps.pop() {
+                Some(info) => info.step,
+                None => {
+                    participant.pending_steps.discard_up_to(next_tick_id);
+                    Step::Forced
+                }
+            };
+            push_combined(&mut combined, *participant_id, step);
+        }
+
+        self.next_tick_id = next_tick_id;
+        self.next_tick_first_received_at = None;
+        self.note_next_tick_received();
+        Some(StepInfo { step: combined, tick_id })
+    }
+
+    fn participant_mut(&mut self, participant_id: ParticipantId) -> Result<&mut Participant<T>, StepsError> {
+        self.participants
+            .get_mut(&participant_id)
+            .ok_or(StepsError::UnknownParticipant(participant_id))
+    }
+
+    /// The participants that are expected to contribute a step for the next tick.
+    fn active_participants(&self) -> impl Iterator<Item = &Participant<T>> + '_ {
+        self.participants
+            .values()
+            .filter(|participant| !participant.joining && !participant.is_waiting_for_reconnect(self.next_tick_id))
+    }
+
+    fn note_next_tick_received(&mut self) {
+        if self.next_tick_first_received_at.is_none()
+            && self
+                .active_participants()
+                .any(|participant| !participant.pending_steps.is_empty())
+        {
+            self.next_tick_first_received_at = Some(self.clock.now());
+        }
+    }
+
+    /// Pushes every tick that can be combined into `steps`, which must expect the next combined tick.
+    /// Returns the number of pushed ticks.
+    pub fn combine_into(&mut self, steps: &mut Steps<T>) -> Result<usize, StepsError> {
+        steps.check_next_write(self.next_tick_id)?;
+
+        let mut count = 0;
+        while let Some(info) = self.pop_combined() {
+            steps.push(info.step);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+/// [`StepCombinator::add_participant`] limits the participants to what fits in one [`ParticipantSteps`].
+fn push_combined<T>(combined: &mut ParticipantSteps<T>, participant_id: ParticipantId, step: Step<T>) {
+    combined
+        .push(participant_id, step)
+        .expect("every participant has room in the combined steps");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use crate::Step::Custom;
+    use crate::{read_octets, DecodeError, Deserialize, ParticipantIdValue, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum GameInput {
+        Move(i8),
+    }
+
+    impl Serialize for GameInput {
+        fn serialize(&self, buffer: &mut Vec<u8>) {
+            let GameInput::Move(direction) = self;
+            buffer.push(*direction as u8);
+        }
+    }
+
+    impl Deserialize for GameInput {
+        fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+            let [direction] = read_octets(bytes)?;
+            Ok((GameInput::Move(direction as i8), 1))
+        }
+    }
+
+    /// Adds the participants and combines the tick where they joined.
+    fn join<C: Clock>(combinator: &mut StepCombinator<GameInput, C>, participant_ids: &[ParticipantIdValue]) {
+        for participant_id in participant_ids {
+            combinator.add_participant(ParticipantId(*participant_id)).unwrap();
+        }
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.step.len(), participant_ids.len());
+        assert!(combined.step.iter().all(|participant_step| participant_step.step == Step::Joined));
+    }
+
+    #[test]
+    fn combines_when_every_participant_contributed() {
+        let mut combinator = StepCombinator::new(16, TickId(9));
+        join(&mut combinator, &[2, 1]);
+        assert_eq!(
+            combinator.add_participant(ParticipantId(1)),
+            Err(StepsError::DuplicateParticipant(ParticipantId(1)))
+        );
+
+        combinator.receive(ParticipantId(2), TickId(11), GameInput::Move(21)).unwrap();
+        combinator.receive(ParticipantId(2), TickId(10), GameInput::Move(20)).unwrap();
+        assert!(combinator.pop_combined().is_none());
+
+        combinator.receive(ParticipantId(1), TickId(10), GameInput::Move(10)).unwrap();
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(10));
+        let ids: Vec<ParticipantId> = combined.step.iter().map(|step| step.participant_id).collect();
+        assert_eq!(ids, vec![ParticipantId(1), ParticipantId(2)]);
+        assert_eq!(combined.step.get(ParticipantId(2)), Some(&Custom(GameInput::Move(20))));
+
+        assert!(combinator.pop_combined().is_none());
+        assert_eq!(combinator.next_tick_id(), TickId(11));
+    }
+
+    #[test]
+    fn combine_into_steps() {
+        let mut combinator = StepCombinator::new(16, TickId(u32::MAX));
+        let mut steps = Steps::new();
+        join(&mut combinator, &[0, 1]);
+
+        for tick in 0..4 {
+            combinator.receive(ParticipantId(0), TickId(tick), GameInput::Move(0)).unwrap();
+        }
+        for tick in [2, 0, 1] {
+            combinator.receive(ParticipantId(1), TickId(tick), GameInput::Move(1)).unwrap();
+        }
+        assert_eq!(
+            combinator.receive(ParticipantId(1), TickId(1), GameInput::Move(1)),
+            Ok(SetOutcome::IgnoredDuplicate)
+        );
+
+        assert_eq!(combinator.combine_into(&mut steps), Ok(3));
+        assert_eq!(steps.back_tick_id(), Some(TickId(2)));
+        assert_eq!(steps.get(TickId(1)).unwrap().step.len(), 2);
+
+        assert_eq!(
+            combinator.receive(ParticipantId(1), TickId(0), GameInput::Move(1)),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        assert_eq!(
+            combinator.receive(ParticipantId(5), TickId(3), GameInput::Move(1)),
+            Err(StepsError::UnknownParticipant(ParticipantId(5)))
+        );
+
+        combinator.remove_participant(ParticipantId(1)).unwrap();
+        assert_eq!(combinator.combine_into(&mut steps), Ok(1));
+        assert_eq!(steps.get(TickId(3)).unwrap().step.get(ParticipantId(1)), Some(&Step::Left));
+
+        let mut other_steps = Steps::new_with_initial_tick(TickId(2));
+        assert!(combinator.combine_into(&mut other_steps).is_err());
+    }
+
+    #[test]
+    fn late_joining_participant_joins_at_next_tick() {
+        let mut combinator = StepCombinator::new(8, TickId(0));
+        combinator.add_participant(ParticipantId(0)).unwrap();
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(0));
+        assert_eq!(combined.step.get(ParticipantId(0)), Some(&Step::Joined));
+
+        combinator.receive(ParticipantId(0), TickId(1), GameInput::Move(0)).unwrap();
+        combinator.receive(ParticipantId(0), TickId(2), GameInput::Move(0)).unwrap();
+        assert!(combinator.pop_combined().is_some());
+
+        combinator.add_participant(ParticipantId(1)).unwrap();
+        assert_eq!(
+            combinator.receive(ParticipantId(1), TickId(2), GameInput::Move(1)),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(2));
+        assert_eq!(combined.step.get(ParticipantId(0)), Some(&Custom(GameInput::Move(0))));
+        assert_eq!(combined.step.get(ParticipantId(1)), Some(&Step::Joined));
+
+        assert!(combinator.pop_combined().is_none());
+        combinator.receive(ParticipantId(0), TickId(3), GameInput::Move(0)).unwrap();
+        combinator.receive(ParticipantId(1), TickId(3), GameInput::Move(1)).unwrap();
+        assert_eq!(combinator.pop_combined().unwrap().step.len(), 2);
+    }
+
+    #[test]
+    fn removed_participant_leaves_at_next_tick() {
+        let mut combinator = StepCombinator::new(8, TickId(20));
+        join(&mut combinator, &[0, 1]);
+
+        combinator.remove_participant(ParticipantId(1)).unwrap();
+        assert!(!combinator.contains_participant(ParticipantId(1)));
+        assert_eq!(
+            combinator.add_participant(ParticipantId(1)),
+            Err(StepsError::DuplicateParticipant(ParticipantId(1)))
+        );
+        assert!(combinator.pop_combined().is_none());
+
+        combinator.receive(ParticipantId(0), TickId(21), GameInput::Move(0)).unwrap();
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.step.get(ParticipantId(0)), Some(&Custom(GameInput::Move(0))));
+        assert_eq!(combined.step.get(ParticipantId(1)), Some(&Step::Left));
+
+        // A participant that is removed before its join tick is combined never shows up.
+        combinator.add_participant(ParticipantId(1)).unwrap();
+        combinator.add_participant(ParticipantId(2)).unwrap();
+        combinator.remove_participant(ParticipantId(2)).unwrap();
+        combinator.receive(ParticipantId(0), TickId(22), GameInput::Move(0)).unwrap();
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(22));
+        assert_eq!(combined.step.len(), 2);
+        assert_eq!(combined.step.get(ParticipantId(1)), Some(&Step::Joined));
+    }
+
+    #[test]
+    fn roster_changes_survive_serialization() {
+        let mut combinator = StepCombinator::<GameInput>::new(8, TickId(0));
+        let mut steps = Steps::new();
+        combinator.add_participant(ParticipantId(3)).unwrap();
+        combinator.combine_into(&mut steps).unwrap();
+        combinator.remove_participant(ParticipantId(3)).unwrap();
+        combinator.combine_into(&mut steps).unwrap();
+
+        let mut buffer = Vec::new();
+        for info in &steps {
+            info.serialize(&mut buffer);
+        }
+        let (joined, octet_count) = StepInfo::<GameInput>::deserialize(&buffer).unwrap();
+        let (left, _) = StepInfo::<GameInput>::deserialize(&buffer[octet_count..]).unwrap();
+        assert_eq!(joined.step.get(ParticipantId(3)), Some(&Step::Joined));
+        assert_eq!(left.tick_id, TickId(1));
+        assert_eq!(left.step.get(ParticipantId(3)), Some(&Step::Left));
+    }
+
+    #[derive(Clone)]
+    struct TestClock(Rc<Cell<Instant>>);
+
+    impl TestClock {
+        fn advance(&self, duration: Duration) {
+            self.0.set(self.0.get() + duration);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> Instant {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn force_after_tick_deadline() {
+        let mut combinator = StepCombinator::new(16, TickId(u32::MAX));
+        combinator.set_deadline(Some(Deadline::Ticks(3)));
+        join(&mut combinator, &[0, 1]);
+
+        for tick in 0..3 {
+            combinator.receive(ParticipantId(0), TickId(tick), GameInput::Move(0)).unwrap();
+        }
+        assert!(combinator.pop_combined().is_none());
+
+        combinator.receive(ParticipantId(0), TickId(3), GameInput::Move(0)).unwrap();
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(0));
+        assert_eq!(combined.step.get(ParticipantId(1)), Some(&Step::Forced));
+        assert_eq!(combined.step.get(ParticipantId(0)), Some(&Custom(GameInput::Move(0))));
+        assert!(combinator.pop_combined().is_none());
+
+        assert_eq!(
+            combinator.receive(ParticipantId(1), TickId(0), GameInput::Move(1)),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        combinator.receive(ParticipantId(1), TickId(1), GameInput::Move(1)).unwrap();
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.step.get(ParticipantId(1)), Some(&Custom(GameInput::Move(1))));
+        assert_eq!(combined.step.forced_participant_ids().count(), 0);
+    }
+
+    #[test]
+    fn force_after_duration_deadline() {
+        let clock = TestClock(Rc::new(Cell::new(Instant::now())));
+        let mut combinator = StepCombinator::with_clock(16, TickId(4), clock.clone());
+        combinator.set_deadline(Some(Deadline::Duration(Duration::from_millis(100))));
+        join(&mut combinator, &[0, 1]);
+
+        clock.advance(Duration::from_secs(10));
+        assert!(combinator.pop_combined().is_none());
+
+        combinator.receive(ParticipantId(1), TickId(5), GameInput::Move(1)).unwrap();
+        combinator.receive(ParticipantId(1), TickId(6), GameInput::Move(1)).unwrap();
+        clock.advance(Duration::from_millis(99));
+        assert!(combinator.pop_combined().is_none());
+
+        clock.advance(Duration::from_millis(1));
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(5));
+        assert_eq!(combined.step.forced_participant_ids().collect::<Vec<_>>(), vec![ParticipantId(0)]);
+
+        // The deadline for the following tick starts when it becomes the next tick.
+        assert!(combinator.pop_combined().is_none());
+        clock.advance(Duration::from_millis(100));
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(6));
+        assert_eq!(combined.step.get(ParticipantId(0)), Some(&Step::Forced));
+    }
+
+    #[test]
+    fn never_force_without_deadline() {
+        let mut combinator = StepCombinator::new(16, TickId(u32::MAX));
+        join(&mut combinator, &[0, 1]);
+        for tick in 0..16 {
+            combinator.receive(ParticipantId(0), TickId(tick), GameInput::Move(0)).unwrap();
+        }
+        assert!(!combinator.is_next_tick_overdue());
+        assert!(combinator.pop_combined().is_none());
+    }
+
+    #[test]
+    fn disconnected_participant_waits_for_reconnect() {
+        let mut combinator = StepCombinator::new(16, TickId(u32::MAX));
+        join(&mut combinator, &[0, 1]);
+        for tick in 0..6 {
+            combinator.receive(ParticipantId(0), TickId(tick), GameInput::Move(0)).unwrap();
+        }
+        combinator.receive(ParticipantId(1), TickId(0), GameInput::Move(1)).unwrap();
+        assert_eq!(combinator.pop_combined().unwrap().step.get(ParticipantId(1)), Some(&Custom(GameInput::Move(1))));
+
+        combinator.disconnect(ParticipantId(1)).unwrap();
+        assert_eq!(combinator.connection_state(ParticipantId(1)), Some(ConnectionState::Disconnected));
+        assert_eq!(
+            combinator.receive(ParticipantId(1), TickId(1), GameInput::Move(1)),
+            Err(StepsError::ParticipantDisconnected(ParticipantId(1)))
+        );
+        for tick in 1..3 {
+            let combined = combinator.pop_combined().unwrap();
+            assert_eq!(combined.tick_id, TickId(tick));
+            assert_eq!(combined.step.get(ParticipantId(1)), Some(&Step::WaitingForReconnect));
+            assert_eq!(combined.step.get(ParticipantId(0)), Some(&Custom(GameInput::Move(0))));
+        }
+
+        assert_eq!(
+            combinator.reconnect(ParticipantId(1), TickId(2)),
+            Err(StepsError::TickTooOld {
+                tick_id: TickId(2),
+                min_tick_id: TickId(3)
+            })
+        );
+        combinator.reconnect(ParticipantId(1), TickId(5)).unwrap();
+        assert_eq!(
+            combinator.connection_state(ParticipantId(1)),
+            Some(ConnectionState::Reconnecting {
+                resume_tick_id: TickId(5)
+            })
+        );
+        assert_eq!(
+            combinator.receive(ParticipantId(1), TickId(4), GameInput::Move(1)),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        combinator.receive(ParticipantId(1), TickId(5), GameInput::Move(1)).unwrap();
+
+        for tick in 3..5 {
+            let combined = combinator.pop_combined().unwrap();
+            assert_eq!(combined.tick_id, TickId(tick));
+            assert_eq!(combined.step.get(ParticipantId(1)), Some(&Step::WaitingForReconnect));
+        }
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(5));
+        assert_eq!(combined.step.get(ParticipantId(1)), Some(&Custom(GameInput::Move(1))));
+        assert_eq!(combinator.connection_state(ParticipantId(1)), Some(ConnectionState::Connected));
+        assert_eq!(
+            combinator.reconnect(ParticipantId(1), TickId(6)),
+            Err(StepsError::ParticipantAlreadyConnected(ParticipantId(1)))
+        );
+    }
+
+    #[test]
+    fn reconnect_before_joined_is_combined() {
+        let mut combinator = StepCombinator::new(8, TickId(10));
+        combinator.add_participant(ParticipantId(0)).unwrap();
+        combinator.disconnect(ParticipantId(0)).unwrap();
+        assert_eq!(
+            combinator.reconnect(ParticipantId(0), TickId(10)),
+            Err(StepsError::TickTooOld {
+                tick_id: TickId(10),
+                min_tick_id: TickId(11)
+            })
+        );
+
+        combinator.reconnect(ParticipantId(0), TickId(11)).unwrap();
+        assert_eq!(combinator.connection_state(ParticipantId(0)), Some(ConnectionState::Connected));
+        assert_eq!(
+            combinator.receive(ParticipantId(0), TickId(10), GameInput::Move(0)),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        combinator.receive(ParticipantId(0), TickId(11), GameInput::Move(1)).unwrap();
+        assert_eq!(combinator.pop_combined().unwrap().step.get(ParticipantId(0)), Some(&Step::Joined));
+        let combined = combinator.pop_combined().unwrap();
+        assert_eq!(combined.tick_id, TickId(11));
+        assert_eq!(combined.step.get(ParticipantId(0)), Some(&Custom(GameInput::Move(1))));
+    }
+
+    #[test]
+    fn all_disconnected_does_not_advance() {
+        let mut combinator = StepCombinator::<GameInput>::new(16, TickId(0));
+        combinator.set_deadline(Some(Deadline::Ticks(1)));
+        combinator.add_participant(ParticipantId(0)).unwrap();
+        combinator.disconnect(ParticipantId(0)).unwrap();
+        assert_eq!(combinator.pop_combined().unwrap().step.get(ParticipantId(0)), Some(&Step::Joined));
+        assert!(combinator.pop_combined().is_none());
+
+        combinator.reconnect(ParticipantId(0), TickId(1)).unwrap();
+        assert_eq!(combinator.connection_state(ParticipantId(0)), Some(ConnectionState::Connected));
+        combinator.receive(ParticipantId(0), TickId(1), GameInput::Move(0)).unwrap();
+        assert_eq!(combinator.pop_combined().unwrap().tick_id, TickId(1));
+    }
+}
@@ -2,30 +2,224 @@
  *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
  *  Licensed under the MIT License. See LICENSE in the project root for license information.
  *--------------------------------------------------------------------------------------------------------*/
-use std::collections::VecDeque;
+use std::collections::{vec_deque, VecDeque};
+use std::slice;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
 
 use tick_id::TickId;
 
+use crate::sequence::TickSequence;
+
+pub mod ack;
+pub mod bounded_steps;
+pub mod combinator;
+pub mod outgoing_steps;
 pub mod pending_steps;
+pub mod reconciliation;
+pub mod sequence;
+pub mod steps_range;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Step<T> {
     Forced,
     WaitingForReconnect,
     Custom(T),
+    /// The participant is part of the game from this tick and contributes steps from the following tick.
+    Joined,
+    /// The participant is no longer part of the game after this tick.
+    Left,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnexpectedEnd { needed: usize, remaining: usize },
+    UnknownStepType(u8),
+    InvalidValue(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd { needed, remaining } => write!(
+                f,
+                "unexpected end of buffer: needed {} octets, {} remaining",
+                needed, remaining
+            ),
+            DecodeError::UnknownStepType(step_type) => write!(f, "unknown step type {}", step_type),
+            DecodeError::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+#[derive(Debug, PartialEq)]
+pub enum StepsError {
+    TickTooOld { tick_id: TickId, min_tick_id: TickId },
+    TickTooFarAhead { tick_id: TickId, max_tick_id: TickId },
+    DuplicateTick(TickId),
+    ConflictingDuplicate(TickId),
+    UnexpectedTick { tick_id: TickId, expected_tick_id: TickId },
+    DuplicateParticipant(ParticipantId),
+    UnknownParticipant(ParticipantId),
+    ParticipantDisconnected(ParticipantId),
+    ParticipantAlreadyConnected(ParticipantId),
+    CapacityExceeded { capacity: usize },
+    Backpressure { capacity: usize },
 }
 
+impl fmt::Display for StepsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StepsError::TickTooOld { tick_id, min_tick_id } => {
+                write!(f, "{} is too old, oldest accepted is {}", tick_id, min_tick_id)
+            }
+            StepsError::TickTooFarAhead { tick_id, max_tick_id } => {
+                write!(f, "{} is too far ahead, newest accepted is {}", tick_id, max_tick_id)
+            }
+            StepsError::DuplicateTick(tick_id) => write!(f, "{} was already received", tick_id),
+            StepsError::ConflictingDuplicate(tick_id) => {
+                write!(f, "{} was already received with a different step", tick_id)
+            }
+            StepsError::UnexpectedTick {
+                tick_id,
+                expected_tick_id,
+            } => write!(f, "found {} but expected {}", tick_id, expected_tick_id),
+            StepsError::DuplicateParticipant(participant_id) => {
+                write!(f, "{} already has a step for this tick", participant_id)
+            }
+            StepsError::UnknownParticipant(participant_id) => write!(f, "{} is not known", participant_id),
+            StepsError::ParticipantDisconnected(participant_id) => write!(f, "{} is disconnected", participant_id),
+            StepsError::ParticipantAlreadyConnected(participant_id) => {
+                write!(f, "{} is already connected", participant_id)
+            }
+            StepsError::CapacityExceeded { capacity } => write!(f, "capacity of {} steps exceeded", capacity),
+            StepsError::Backpressure { capacity } => {
+                write!(f, "capacity of {} steps reached, retry when steps are consumed", capacity)
+            }
+        }
+    }
+}
+
+impl Error for StepsError {}
+
+/// Decodes a value from the start of `bytes`, returning it together with the number of octets consumed,
+/// so that several values can be decoded back to back from the same buffer.
 pub trait Deserialize {
-    fn deserialize(bytes: &[u8]) -> Self where Self : Sized;
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> where Self : Sized;
+}
+
+pub trait Serialize {
+    fn serialize(&self, buffer: &mut Vec<u8>);
+}
+
+impl<S: Serialize + ?Sized> Serialize for &S {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        (**self).serialize(buffer)
+    }
+}
+
+pub(crate) fn read_octets<const N: usize>(bytes: &[u8]) -> Result<[u8; N], DecodeError> {
+    bytes
+        .get(..N)
+        .and_then(|octets| octets.try_into().ok())
+        .ok_or(DecodeError::UnexpectedEnd {
+            needed: N,
+            remaining: bytes.len(),
+        })
+}
+
+const STEP_TYPE_FORCED: u8 = 0x01;
+const STEP_TYPE_WAITING_FOR_RECONNECT: u8 = 0x02;
+const STEP_TYPE_CUSTOM: u8 = 0x03;
+const STEP_TYPE_JOINED: u8 = 0x04;
+const STEP_TYPE_LEFT: u8 = 0x05;
+
+impl<T: Serialize> Serialize for Step<T> {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Step::Forced => buffer.push(STEP_TYPE_FORCED),
+            Step::WaitingForReconnect => buffer.push(STEP_TYPE_WAITING_FOR_RECONNECT),
+            Step::Custom(custom) => {
+                buffer.push(STEP_TYPE_CUSTOM);
+                custom.serialize(buffer);
+            }
+            Step::Joined => buffer.push(STEP_TYPE_JOINED),
+            Step::Left => buffer.push(STEP_TYPE_LEFT),
+        }
+    }
 }
 
+impl<T: Deserialize> Deserialize for Step<T> {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let [step_type] = read_octets(bytes)?;
+        match step_type {
+            STEP_TYPE_FORCED => Ok((Step::Forced, 1)),
+            STEP_TYPE_WAITING_FOR_RECONNECT => Ok((Step::WaitingForReconnect, 1)),
+            STEP_TYPE_CUSTOM => {
+                let (custom, octet_count) = T::deserialize(&bytes[1..])?;
+                Ok((Step::Custom(custom), 1 + octet_count))
+            }
+            STEP_TYPE_JOINED => Ok((Step::Joined, 1)),
+            STEP_TYPE_LEFT => Ok((Step::Left, 1)),
+            _ => Err(DecodeError::UnknownStepType(step_type)),
+        }
+    }
+}
+
+/// Wide enough for sessions with more than 256 participants.
+pub type ParticipantIdValue = u16;
+
+pub const PARTICIPANT_ID_OCTET_COUNT: usize = std::mem::size_of::<ParticipantIdValue>();
+pub const PARTICIPANT_STEPS_COUNT_OCTET_COUNT: usize = std::mem::size_of::<u16>();
+/// The most steps one [`ParticipantSteps`] can hold, so that the count fits in the same width as an id.
+pub const PARTICIPANT_STEPS_MAX_COUNT: usize = u16::MAX as usize;
+
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct ParticipantId(pub ParticipantIdValue);
+
+impl ParticipantId {
+    pub fn new(value: ParticipantIdValue) -> Self {
+        Self(value)
+    }
+
+    pub fn value(&self) -> ParticipantIdValue {
+        self.0
+    }
+}
+
+impl fmt::Display for ParticipantId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ParticipantId: {}", self.0)
+    }
+}
+
+impl Serialize for ParticipantId {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.0.to_be_bytes());
+    }
+}
+
+impl Deserialize for ParticipantId {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let value = ParticipantIdValue::from_be_bytes(read_octets::<PARTICIPANT_ID_OCTET_COUNT>(bytes)?);
+        Ok((Self(value), PARTICIPANT_ID_OCTET_COUNT))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 pub struct ParticipantStep<T> {
-    pub participant_id: u8,
+    pub participant_id: ParticipantId,
     pub step: Step<T>,
 }
 
+/// The steps of all participants for a single tick, kept sorted by participant id with at most one step
+/// per participant, so that every peer iterates them in the same order.
+#[derive(Debug, PartialEq, Eq)]
 pub struct ParticipantSteps<T> {
-    pub steps: Vec<ParticipantStep<T>>,
+    steps: Vec<ParticipantStep<T>>,
 }
 
 impl<T> Default for ParticipantSteps<T> {
@@ -35,7 +229,7 @@ impl<T> Default for ParticipantSteps<T> {
 }
 
 impl<T> ParticipantStep<T> {
-    pub fn new(participant_id: u8, step: Step<T>) -> Self {
+    pub fn new(participant_id: ParticipantId, step: Step<T>) -> Self {
         Self {
             participant_id,
             step,
@@ -48,8 +242,64 @@ impl<T> ParticipantSteps<T> {
         Self { steps: Vec::new() }
     }
 
-    pub fn push(&mut self, participant_id: u8, step: Step<T>) {
-        self.steps.push(ParticipantStep::new(participant_id, step));
+    /// Adds the step for `participant_id`, which must not already have a step.
+    pub fn push(&mut self, participant_id: ParticipantId, step: Step<T>) -> Result<(), StepsError> {
+        match self.position(participant_id) {
+            Ok(_) => Err(StepsError::DuplicateParticipant(participant_id)),
+            Err(index) => self.insert_at(index, participant_id, step),
+        }
+    }
+
+    /// Sets the step for `participant_id`, returning the step it replaced.
+    pub fn insert_or_replace(
+        &mut self,
+        participant_id: ParticipantId,
+        step: Step<T>,
+    ) -> Result<Option<Step<T>>, StepsError> {
+        match self.position(participant_id) {
+            Ok(index) => Ok(Some(std::mem::replace(&mut self.steps[index].step, step))),
+            Err(index) => self.insert_at(index, participant_id, step).map(|()| None),
+        }
+    }
+
+    fn insert_at(&mut self, index: usize, participant_id: ParticipantId, step: Step<T>) -> Result<(), StepsError> {
+        if self.steps.len() >= PARTICIPANT_STEPS_MAX_COUNT {
+            return Err(StepsError::CapacityExceeded {
+                capacity: PARTICIPANT_STEPS_MAX_COUNT,
+            });
+        }
+        self.steps.insert(index, ParticipantStep::new(participant_id, step));
+        Ok(())
+    }
+
+    pub fn get(&self, participant_id: ParticipantId) -> Option<&Step<T>> {
+        self.position(participant_id).ok().map(|index| &self.steps[index].step)
+    }
+
+    pub fn get_mut(&mut self, participant_id: ParticipantId) -> Option<&mut Step<T>> {
+        self.position(participant_id).ok().map(|index| &mut self.steps[index].step)
+    }
+
+    pub fn contains(&self, participant_id: ParticipantId) -> bool {
+        self.position(participant_id).is_ok()
+    }
+
+    /// The participants whose input did not arrive in time and got a [`Step::Forced`] instead.
+    pub fn forced_participant_ids(&self) -> impl Iterator<Item = ParticipantId> + '_ {
+        self.steps
+            .iter()
+            .filter(|participant_step| matches!(participant_step.step, Step::Forced))
+            .map(|participant_step| participant_step.participant_id)
+    }
+
+    /// The participant steps in ascending participant id order.
+    pub fn iter(&self) -> slice::Iter<'_, ParticipantStep<T>> {
+        self.steps.iter()
+    }
+
+    fn position(&self, participant_id: ParticipantId) -> Result<usize, usize> {
+        self.steps
+            .binary_search_by_key(&participant_id, |participant_step| participant_step.participant_id)
     }
 
     pub fn len(&self) -> usize {
@@ -61,11 +311,94 @@ impl<T> ParticipantSteps<T> {
     }
 }
 
+impl<T> IntoIterator for ParticipantSteps<T> {
+    type Item = ParticipantStep<T>;
+    type IntoIter = std::vec::IntoIter<ParticipantStep<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.steps.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a ParticipantSteps<T> {
+    type Item = &'a ParticipantStep<T>;
+    type IntoIter = slice::Iter<'a, ParticipantStep<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: Serialize> Serialize for ParticipantStep<T> {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        self.participant_id.serialize(buffer);
+        self.step.serialize(buffer);
+    }
+}
+
+impl<T: Deserialize> Deserialize for ParticipantStep<T> {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let (participant_id, participant_id_octet_count) = ParticipantId::deserialize(bytes)?;
+        let (step, octet_count) = Step::deserialize(&bytes[participant_id_octet_count..])?;
+        Ok((Self::new(participant_id, step), participant_id_octet_count + octet_count))
+    }
+}
+
+impl<T: Serialize> Serialize for ParticipantSteps<T> {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&(self.steps.len() as u16).to_be_bytes());
+        for participant_step in &self.steps {
+            participant_step.serialize(buffer);
+        }
+    }
+}
+
+impl<T: Deserialize> Deserialize for ParticipantSteps<T> {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let count = u16::from_be_bytes(read_octets::<PARTICIPANT_STEPS_COUNT_OCTET_COUNT>(bytes)?);
+        let mut position = PARTICIPANT_STEPS_COUNT_OCTET_COUNT;
+        let mut participant_steps = Self::new();
+        for _ in 0..count {
+            let (participant_step, octet_count) = ParticipantStep::<T>::deserialize(&bytes[position..])?;
+            // Only the canonical, ascending encoding is accepted, so decoding never has to sort.
+            if participant_steps
+                .steps
+                .last()
+                .is_some_and(|previous| previous.participant_id >= participant_step.participant_id)
+            {
+                return Err(DecodeError::InvalidValue(format!(
+                    "{} is not after the previous participant",
+                    participant_step.participant_id
+                )));
+            }
+            participant_steps.steps.push(participant_step);
+            position += octet_count;
+        }
+        Ok((participant_steps, position))
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct StepInfo<T> {
     pub step: ParticipantSteps<T>,
     pub tick_id: TickId,
 }
 
+impl<T: Serialize> Serialize for StepInfo<T> {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.tick_id.value().to_be_bytes());
+        self.step.serialize(buffer);
+    }
+}
+
+impl<T: Deserialize> Deserialize for StepInfo<T> {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let tick_id = TickId(u32::from_be_bytes(read_octets(bytes)?));
+        let (step, octet_count) = ParticipantSteps::deserialize(&bytes[4..])?;
+        Ok((Self { step, tick_id }, 4 + octet_count))
+    }
+}
+
 pub struct Steps<T> {
     steps: VecDeque<StepInfo<T>>,
     expected_read_id: TickId,
@@ -102,26 +435,81 @@ impl<T> Steps<T> {
             tick_id: self.expected_write_id,
         };
         self.steps.push_back(info);
-        self.expected_write_id += 1;
+        self.expected_write_id = self.expected_write_id.wrapping_add(1);
     }
 
-    pub fn pop(&mut self) -> Option<StepInfo<T>> {
-        let info = self.steps.pop_front();
-        if let Some(ref step_info) = info {
-            assert_eq!(step_info.tick_id, self.expected_read_id);
-            self.expected_read_id += 1;
+    /// Pushes `step` for `tick_id`, which must be the next tick to be written.
+    pub fn push_at(&mut self, tick_id: TickId, step: ParticipantSteps<T>) -> Result<(), StepsError> {
+        self.check_next_write(tick_id)?;
+        self.push(step);
+        Ok(())
+    }
+
+    /// Like [`Steps::push_at`], but a tick that is already stored with an identical step is ignored.
+    /// Returns `false` if the step was ignored.
+    pub fn push_at_ignoring_duplicate(&mut self, tick_id: TickId, step: ParticipantSteps<T>) -> Result<bool, StepsError>
+    where
+        T: PartialEq,
+    {
+        match self.check_next_write(tick_id) {
+            Ok(()) => {
+                self.push(step);
+                Ok(true)
+            }
+            Err(StepsError::DuplicateTick(_)) => match self.get(tick_id) {
+                Some(stored) if stored.step == step => Ok(false),
+                _ => Err(StepsError::ConflictingDuplicate(tick_id)),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    fn check_next_write(&self, tick_id: TickId) -> Result<(), StepsError> {
+        let tick_difference = tick_id.wrapping_diff(self.expected_write_id);
+        if tick_difference > 0 {
+            return Err(StepsError::TickTooFarAhead {
+                tick_id,
+                max_tick_id: self.expected_write_id,
+            });
+        }
+        if tick_difference < 0 {
+            if tick_id.is_before(self.expected_read_id) {
+                return Err(StepsError::TickTooOld {
+                    tick_id,
+                    min_tick_id: self.expected_read_id,
+                });
+            }
+            return Err(StepsError::DuplicateTick(tick_id));
+        }
+        Ok(())
+    }
+
+    /// Removes the oldest step. Fails, leaving the step in place, if it is not for the expected read tick.
+    pub fn pop(&mut self) -> Result<Option<StepInfo<T>>, StepsError> {
+        let Some(front_tick_id) = self.front_tick_id() else {
+            return Ok(None);
+        };
+        if front_tick_id != self.expected_read_id {
+            return Err(StepsError::UnexpectedTick {
+                tick_id: front_tick_id,
+                expected_tick_id: self.expected_read_id,
+            });
         }
-        info
+
+        let info = self.steps.pop_front();
+        self.expected_read_id = self.expected_read_id.wrapping_add(1);
+        Ok(info)
     }
 
     pub fn pop_up_to(&mut self, tick_id: TickId) {
         while let Some(info) = self.steps.front() {
-            if info.tick_id >= tick_id {
+            if !info.tick_id.is_before(tick_id) {
                 break;
             }
 
             self.steps.pop_front();
         }
+        self.sync_read_id();
     }
 
     pub fn pop_count(&mut self, count: usize) {
@@ -130,6 +518,34 @@ impl<T> Steps<T> {
         } else {
             self.steps.drain(..count);
         }
+        self.sync_read_id();
+    }
+
+    /// The read position follows the oldest stored step, or the write position when nothing is stored.
+    fn sync_read_id(&mut self) {
+        self.expected_read_id = self.front_tick_id().unwrap_or(self.expected_write_id);
+    }
+
+    /// Removes the newest step, rewinding the write position so that the next push reuses its tick.
+    pub fn pop_back(&mut self) -> Option<StepInfo<T>> {
+        let info = self.steps.pop_back()?;
+        self.expected_write_id = info.tick_id;
+        Some(info)
+    }
+
+    /// Removes every stored step from `tick_id` onward and rewinds the write position to the first removed tick.
+    /// Returns the number of removed steps.
+    pub fn truncate_from(&mut self, tick_id: TickId) -> usize {
+        let Some(front_tick_id) = self.front_tick_id() else {
+            return 0;
+        };
+        let keep_count = tick_id.wrapping_diff(front_tick_id).clamp(0, self.steps.len() as i64) as usize;
+        let removed_count = self.steps.len() - keep_count;
+        if removed_count > 0 {
+            self.expected_write_id = front_tick_id.wrapping_add(keep_count as u32);
+            self.steps.truncate(keep_count);
+        }
+        removed_count
     }
 
     pub fn front_tick_id(&self) -> Option<TickId> {
@@ -140,6 +556,56 @@ impl<T> Steps<T> {
         self.steps.back().map(|step_info| step_info.tick_id)
     }
 
+    pub fn get(&self, tick_id: TickId) -> Option<&StepInfo<T>> {
+        self.index_of(tick_id).map(|index| &self.steps[index])
+    }
+
+    pub fn get_mut(&mut self, tick_id: TickId) -> Option<&mut StepInfo<T>> {
+        self.index_of(tick_id).map(|index| &mut self.steps[index])
+    }
+
+    pub fn contains(&self, tick_id: TickId) -> bool {
+        self.index_of(tick_id).is_some()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, StepInfo<T>> {
+        self.steps.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> vec_deque::IterMut<'_, StepInfo<T>> {
+        self.steps.iter_mut()
+    }
+
+    /// The stored steps with tick ids within `range`, in tick order.
+    pub fn range<R: RangeBounds<TickId>>(&self, range: R) -> vec_deque::Iter<'_, StepInfo<T>> {
+        let Some(front_tick_id) = self.front_tick_id() else {
+            return self.steps.range(..);
+        };
+        let len = self.steps.len() as i64;
+        let start = match range.start_bound() {
+            Bound::Included(&tick_id) => tick_id.wrapping_diff(front_tick_id),
+            Bound::Excluded(&tick_id) => tick_id.wrapping_diff(front_tick_id) + 1,
+            Bound::Unbounded => 0,
+        }
+        .clamp(0, len);
+        let end = match range.end_bound() {
+            Bound::Included(&tick_id) => tick_id.wrapping_diff(front_tick_id) + 1,
+            Bound::Excluded(&tick_id) => tick_id.wrapping_diff(front_tick_id),
+            Bound::Unbounded => len,
+        }
+        .clamp(start, len);
+
+        self.steps.range(start as usize..end as usize)
+    }
+
+    fn index_of(&self, tick_id: TickId) -> Option<usize> {
+        let index = tick_id.wrapping_diff(self.front_tick_id()?);
+        if index < 0 || index >= self.steps.len() as i64 {
+            return None;
+        }
+        Some(index as usize)
+    }
+
     pub fn len(&self) -> usize {
         self.steps.len()
     }
@@ -149,6 +615,49 @@ impl<T> Steps<T> {
     }
 }
 
+impl<T> IntoIterator for Steps<T> {
+    type Item = StepInfo<T>;
+    type IntoIter = vec_deque::IntoIter<StepInfo<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.steps.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Steps<T> {
+    type Item = &'a StepInfo<T>;
+    type IntoIter = vec_deque::Iter<'a, StepInfo<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Steps<T> {
+    type Item = &'a mut StepInfo<T>;
+    type IntoIter = vec_deque::IterMut<'a, StepInfo<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T> Extend<ParticipantSteps<T>> for Steps<T> {
+    fn extend<I: IntoIterator<Item = ParticipantSteps<T>>>(&mut self, iter: I) {
+        for step in iter {
+            self.push(step);
+        }
+    }
+}
+
+impl<T> FromIterator<ParticipantSteps<T>> for Steps<T> {
+    fn from_iter<I: IntoIterator<Item = ParticipantSteps<T>>>(iter: I) -> Self {
+        let mut steps = Self::new();
+        steps.extend(iter);
+        steps
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::Step::Custom;
@@ -161,10 +670,44 @@ mod tests {
         MoveHorizontal(i32),
     }
 
+    impl Serialize for GameInput {
+        fn serialize(&self, buffer: &mut Vec<u8>) {
+            match self {
+                GameInput::Jumping(jumping) => buffer.extend_from_slice(&[0x01, *jumping as u8]),
+                GameInput::MoveHorizontal(amount) => {
+                    buffer.push(0x02);
+                    buffer.extend_from_slice(&amount.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    impl Deserialize for GameInput {
+        fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+            match read_octets(bytes)? {
+                [0x01] => {
+                    let [_, jumping] = read_octets(bytes)?;
+                    Ok((GameInput::Jumping(jumping != 0), 2))
+                }
+                [0x02] => {
+                    let amount = i32::from_be_bytes(read_octets(&bytes[1..])?);
+                    Ok((GameInput::MoveHorizontal(amount), 5))
+                }
+                [input_type] => Err(DecodeError::InvalidValue(format!("unknown game input {}", input_type))),
+            }
+        }
+    }
+
+    fn single(step: Step<GameInput>) -> ParticipantSteps<GameInput> {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(0), step).unwrap();
+        participant_steps
+    }
+
     #[test]
     fn add_step() {
         let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(23));
-        steps.push(Custom(GameInput::MoveHorizontal(-2)));
+        steps.push(single(Custom(GameInput::MoveHorizontal(-2))));
         assert_eq!(steps.len(), 1);
         assert_eq!(steps.front_tick_id().unwrap().value(), 23)
     }
@@ -172,29 +715,102 @@ mod tests {
     #[test]
     fn push_and_pop_step() {
         let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(23));
-        steps.push(Custom(GameInput::Jumping(true)));
-        steps.push(Custom(GameInput::MoveHorizontal(42)));
+        steps.push(single(Custom(GameInput::Jumping(true))));
+        steps.push(single(Custom(GameInput::MoveHorizontal(42))));
         assert_eq!(steps.len(), 2);
         assert_eq!(steps.front_tick_id().unwrap().value(), 23);
-        assert_eq!(steps.pop().unwrap().step, Custom(GameInput::Jumping(true)));
+        assert_eq!(steps.pop().unwrap().unwrap().step, single(Custom(GameInput::Jumping(true))));
         assert_eq!(steps.front_tick_id().unwrap().value(), 24);
     }
 
     #[test]
     fn push_and_pop_count() {
         let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(23));
-        steps.push(Custom(GameInput::Jumping(true)));
-        steps.push(Custom(GameInput::MoveHorizontal(42)));
+        steps.push(single(Custom(GameInput::Jumping(true))));
+        steps.push(single(Custom(GameInput::MoveHorizontal(42))));
         assert_eq!(steps.len(), 2);
         steps.pop_count(8);
         assert_eq!(steps.len(), 0);
     }
 
+    #[test]
+    fn pop_after_removals() {
+        let mut steps: Steps<GameInput> = (0..8).map(|amount| single(Custom(GameInput::MoveHorizontal(amount)))).collect();
+        steps.pop_up_to(TickId(2));
+        assert_eq!(steps.pop().unwrap().unwrap().tick_id, TickId(2));
+        steps.pop_count(2);
+        assert_eq!(steps.pop().unwrap().unwrap().tick_id, TickId(5));
+        steps.truncate_from(TickId(7));
+        assert_eq!(steps.pop().unwrap().unwrap().tick_id, TickId(6));
+        assert!(steps.pop().unwrap().is_none());
+
+        steps.push(single(Step::Forced));
+        steps.pop_count(5);
+        steps.push(single(Step::Forced));
+        assert_eq!(steps.pop().unwrap().unwrap().tick_id, TickId(8));
+    }
+
+    #[test]
+    fn pop_with_broken_read_position() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(3));
+        steps.push(single(Step::Forced));
+        steps.expected_read_id = TickId(1);
+        assert_eq!(
+            steps.pop(),
+            Err(StepsError::UnexpectedTick {
+                tick_id: TickId(3),
+                expected_tick_id: TickId(1)
+            })
+        );
+        assert_eq!(steps.len(), 1);
+    }
+
+    #[test]
+    fn push_at_next_tick_only() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(10));
+        assert_eq!(
+            steps.push_at(TickId(11), single(Step::Forced)),
+            Err(StepsError::TickTooFarAhead {
+                tick_id: TickId(11),
+                max_tick_id: TickId(10)
+            })
+        );
+        assert_eq!(steps.push_at(TickId(10), single(Step::Forced)), Ok(()));
+        assert_eq!(steps.push_at(TickId(11), single(Step::Forced)), Ok(()));
+        assert_eq!(
+            steps.push_at(TickId(10), single(Step::Forced)),
+            Err(StepsError::DuplicateTick(TickId(10)))
+        );
+        steps.pop().unwrap();
+        assert_eq!(
+            steps.push_at(TickId(10), single(Step::Forced)),
+            Err(StepsError::TickTooOld {
+                tick_id: TickId(10),
+                min_tick_id: TickId(11)
+            })
+        );
+        assert_eq!(steps.len(), 1);
+    }
+
+    #[test]
+    fn push_at_ignoring_identical_duplicates() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(10));
+        let jump = || single(Custom(GameInput::Jumping(true)));
+        assert_eq!(steps.push_at_ignoring_duplicate(TickId(10), jump()), Ok(true));
+        assert_eq!(steps.push_at_ignoring_duplicate(TickId(10), jump()), Ok(false));
+        assert_eq!(
+            steps.push_at_ignoring_duplicate(TickId(10), single(Step::Forced)),
+            Err(StepsError::ConflictingDuplicate(TickId(10)))
+        );
+        assert!(steps.push_at_ignoring_duplicate(TickId(12), jump()).is_err());
+        assert_eq!(steps.len(), 1);
+    }
+
     #[test]
     fn push_and_pop_up_to_lower() {
         let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(23));
-        steps.push(Custom(GameInput::Jumping(true)));
-        steps.push(Custom(GameInput::MoveHorizontal(42)));
+        steps.push(single(Custom(GameInput::Jumping(true))));
+        steps.push(single(Custom(GameInput::MoveHorizontal(42))));
         assert_eq!(steps.len(), 2);
         steps.pop_up_to(TickId(1));
         assert_eq!(steps.len(), 2);
@@ -203,10 +819,297 @@ mod tests {
     #[test]
     fn push_and_pop_up_to_equal() {
         let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(23));
-        steps.push(Custom(GameInput::Jumping(true)));
-        steps.push(Custom(GameInput::MoveHorizontal(42)));
+        steps.push(single(Custom(GameInput::Jumping(true))));
+        steps.push(single(Custom(GameInput::MoveHorizontal(42))));
         assert_eq!(steps.len(), 2);
         steps.pop_up_to(TickId::new(24));
         assert_eq!(steps.len(), 1);
     }
+
+    #[test]
+    fn get_by_tick_id() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(23));
+        assert!(steps.get(TickId(23)).is_none());
+        steps.push(single(Custom(GameInput::Jumping(true))));
+        steps.push(single(Custom(GameInput::MoveHorizontal(42))));
+        steps.push(single(Custom(GameInput::MoveHorizontal(43))));
+        steps.pop_up_to(TickId(24));
+
+        assert!(!steps.contains(TickId(23)));
+        assert!(steps.contains(TickId(24)));
+        assert!(steps.contains(TickId(25)));
+        assert!(!steps.contains(TickId(26)));
+        assert_eq!(steps.get(TickId(25)).unwrap().step, single(Custom(GameInput::MoveHorizontal(43))));
+
+        steps.get_mut(TickId(24)).unwrap().step = single(Step::Forced);
+        assert_eq!(steps.get(TickId(24)).unwrap().tick_id, TickId(24));
+        assert_eq!(steps.get(TickId(24)).unwrap().step, single(Step::Forced));
+    }
+
+    #[test]
+    fn iterate_steps() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(10));
+        steps.extend((0..5).map(|amount| single(Custom(GameInput::MoveHorizontal(amount)))));
+        steps.pop_up_to(TickId(11));
+
+        let tick_ids: Vec<u32> = steps.iter().map(|info| info.tick_id.value()).collect();
+        assert_eq!(tick_ids, vec![11, 12, 13, 14]);
+
+        for info in &mut steps {
+            *info.step.get_mut(ParticipantId(0)).unwrap() = Step::Forced;
+        }
+        assert!((&steps).into_iter().all(|info| info.step == single(Step::Forced)));
+
+        let owned: Vec<StepInfo<GameInput>> = steps.into_iter().collect();
+        assert_eq!(owned.len(), 4);
+        assert_eq!(owned[0].tick_id, TickId(11));
+    }
+
+    #[test]
+    fn range_of_steps() {
+        let steps: Steps<GameInput> = (0..5).map(|amount| single(Custom(GameInput::MoveHorizontal(amount)))).collect();
+        let tick_ids = |iter: vec_deque::Iter<'_, StepInfo<GameInput>>| -> Vec<u32> {
+            iter.map(|info| info.tick_id.value()).collect()
+        };
+
+        assert_eq!(tick_ids(steps.range(TickId(1)..TickId(3))), vec![1, 2]);
+        assert_eq!(tick_ids(steps.range(TickId(1)..=TickId(3))), vec![1, 2, 3]);
+        assert_eq!(tick_ids(steps.range(TickId(3)..)), vec![3, 4]);
+        assert_eq!(tick_ids(steps.range(..TickId(2))), vec![0, 1]);
+        assert_eq!(tick_ids(steps.range(TickId(4)..TickId(100))), vec![4]);
+        assert_eq!(tick_ids(steps.range(TickId(7)..TickId(9))), Vec::<u32>::new());
+        assert_eq!(tick_ids(steps.range(TickId(3)..TickId(1))), Vec::<u32>::new());
+        assert_eq!(tick_ids(Steps::<GameInput>::new().range(..)), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn pop_back_rewinds_write_position() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(50));
+        assert!(steps.pop_back().is_none());
+        steps.push(single(Custom(GameInput::Jumping(true))));
+        steps.push(single(Custom(GameInput::Jumping(false))));
+
+        let last = steps.pop_back().unwrap();
+        assert_eq!(last.tick_id, TickId(51));
+        assert_eq!(last.step, single(Custom(GameInput::Jumping(false))));
+
+        steps.push(single(Custom(GameInput::MoveHorizontal(1))));
+        assert_eq!(steps.back_tick_id(), Some(TickId(51)));
+        assert_eq!(steps.get(TickId(51)).unwrap().step, single(Custom(GameInput::MoveHorizontal(1))));
+    }
+
+    #[test]
+    fn truncate_predicted_steps() {
+        let mut steps: Steps<GameInput> = (0..6).map(|amount| single(Custom(GameInput::MoveHorizontal(amount)))).collect();
+        steps.pop_up_to(TickId(2));
+
+        assert_eq!(steps.truncate_from(TickId(10)), 0);
+        assert_eq!(steps.back_tick_id(), Some(TickId(5)));
+
+        assert_eq!(steps.truncate_from(TickId(4)), 2);
+        assert_eq!(steps.back_tick_id(), Some(TickId(3)));
+        steps.push(single(Step::Forced));
+        assert_eq!(steps.back_tick_id(), Some(TickId(4)));
+        assert_eq!(steps.get(TickId(4)).unwrap().step, single(Step::Forced));
+
+        assert_eq!(steps.truncate_from(TickId(0)), 3);
+        assert!(steps.is_empty());
+        steps.push(single(Step::Forced));
+        assert_eq!(steps.front_tick_id(), Some(TickId(2)));
+    }
+
+    #[test]
+    fn steps_across_tick_wrap_around() {
+        let mut steps = Steps::<GameInput>::new_with_initial_tick(TickId(u32::MAX - 1));
+        steps.extend((0..5).map(|amount| single(Custom(GameInput::MoveHorizontal(amount)))));
+        assert_eq!(steps.back_tick_id(), Some(TickId(2)));
+        assert_eq!(steps.get(TickId(1)).unwrap().step, single(Custom(GameInput::MoveHorizontal(3))));
+        let tick_ids: Vec<u32> = steps.range(TickId(u32::MAX)..TickId(2)).map(|info| info.tick_id.value()).collect();
+        assert_eq!(tick_ids, vec![u32::MAX, 0, 1]);
+
+        steps.pop_up_to(TickId(0));
+        assert_eq!(steps.front_tick_id(), Some(TickId(0)));
+        assert_eq!(steps.pop().unwrap().unwrap().tick_id, TickId(0));
+        assert_eq!(steps.push_at(TickId(3), single(Step::Forced)), Ok(()));
+        assert_eq!(steps.truncate_from(TickId(u32::MAX)), 3);
+        assert_eq!(steps.push_at(TickId(1), single(Step::Forced)), Ok(()));
+    }
+
+    #[test]
+    fn participant_steps_are_sorted_and_unique() {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(7), Step::Forced).unwrap();
+        participant_steps.push(ParticipantId(2), Custom(GameInput::Jumping(true))).unwrap();
+        participant_steps.push(ParticipantId(4), Step::WaitingForReconnect).unwrap();
+        assert_eq!(
+            participant_steps.push(ParticipantId(2), Step::Forced),
+            Err(StepsError::DuplicateParticipant(ParticipantId(2)))
+        );
+
+        let ids: Vec<ParticipantId> = participant_steps.iter().map(|step| step.participant_id).collect();
+        assert_eq!(ids, vec![ParticipantId(2), ParticipantId(4), ParticipantId(7)]);
+        assert!(participant_steps.contains(ParticipantId(4)));
+        assert!(!participant_steps.contains(ParticipantId(3)));
+        assert_eq!(participant_steps.get(ParticipantId(2)), Some(&Custom(GameInput::Jumping(true))));
+
+        assert_eq!(
+            participant_steps.insert_or_replace(ParticipantId(2), Step::Forced),
+            Ok(Some(Custom(GameInput::Jumping(true))))
+        );
+        assert_eq!(participant_steps.insert_or_replace(ParticipantId(0), Step::Forced), Ok(None));
+        let ids: Vec<ParticipantId> = participant_steps.into_iter().map(|step| step.participant_id).collect();
+        assert_eq!(ids, vec![ParticipantId(0), ParticipantId(2), ParticipantId(4), ParticipantId(7)]);
+    }
+
+    #[test]
+    fn arrival_order_does_not_matter() {
+        let mut first = ParticipantSteps::new();
+        first.push(ParticipantId(1), Custom(GameInput::Jumping(true))).unwrap();
+        first.push(ParticipantId(0), Custom(GameInput::MoveHorizontal(2))).unwrap();
+        let mut second = ParticipantSteps::new();
+        second.push(ParticipantId(0), Custom(GameInput::MoveHorizontal(2))).unwrap();
+        second.push(ParticipantId(1), Custom(GameInput::Jumping(true))).unwrap();
+        assert_eq!(first, second);
+
+        let mut first_buffer = Vec::new();
+        first.serialize(&mut first_buffer);
+        let mut second_buffer = Vec::new();
+        second.serialize(&mut second_buffer);
+        assert_eq!(first_buffer, second_buffer);
+    }
+
+    #[test]
+    fn deserialize_descending_participants() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&2u16.to_be_bytes());
+        ParticipantStep::<GameInput>::new(ParticipantId(5), Step::Forced).serialize(&mut buffer);
+        ParticipantStep::<GameInput>::new(ParticipantId(4), Step::Forced).serialize(&mut buffer);
+        assert!(matches!(
+            ParticipantSteps::<GameInput>::deserialize(&buffer),
+            Err(DecodeError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_duplicate_participant() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&2u16.to_be_bytes());
+        for _ in 0..2 {
+            ParticipantStep::<GameInput>::new(ParticipantId(3), Step::Forced).serialize(&mut buffer);
+        }
+        assert!(matches!(
+            ParticipantSteps::<GameInput>::deserialize(&buffer),
+            Err(DecodeError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn serialize_most_participant_steps() {
+        let mut participant_steps = ParticipantSteps::<GameInput>::new();
+        for value in 0..ParticipantIdValue::MAX {
+            participant_steps.push(ParticipantId(value), Step::Forced).unwrap();
+        }
+        let capacity_exceeded = Err(StepsError::CapacityExceeded {
+            capacity: PARTICIPANT_STEPS_MAX_COUNT,
+        });
+        assert_eq!(
+            participant_steps.push(ParticipantId(ParticipantIdValue::MAX), Step::Forced),
+            capacity_exceeded
+        );
+        assert_eq!(
+            participant_steps.insert_or_replace(ParticipantId(ParticipantIdValue::MAX), Step::Forced),
+            capacity_exceeded.map(|()| None)
+        );
+        assert!(participant_steps.insert_or_replace(ParticipantId(0), Step::Forced).is_ok());
+
+        let mut buffer = Vec::new();
+        participant_steps.serialize(&mut buffer);
+        let (decoded, octet_count) = ParticipantSteps::<GameInput>::deserialize(&buffer).unwrap();
+        assert_eq!(octet_count, buffer.len());
+        assert_eq!(decoded.len(), PARTICIPANT_STEPS_MAX_COUNT);
+    }
+
+    #[test]
+    fn serialize_and_deserialize_participant_id() {
+        let participant_id = ParticipantId::new(42);
+        let mut buffer = Vec::new();
+        participant_id.serialize(&mut buffer);
+        assert_eq!(buffer.len(), PARTICIPANT_ID_OCTET_COUNT);
+        assert_eq!(ParticipantId::deserialize(&buffer), Ok((participant_id, PARTICIPANT_ID_OCTET_COUNT)));
+        assert!(ParticipantId::deserialize(&buffer[1..]).is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize_step() {
+        for step in [Step::Forced, Step::WaitingForReconnect, Custom(GameInput::MoveHorizontal(-99))] {
+            let mut buffer = Vec::new();
+            step.serialize(&mut buffer);
+            assert_eq!(Step::<GameInput>::deserialize(&buffer), Ok((step, buffer.len())));
+        }
+    }
+
+    #[test]
+    fn serialize_and_deserialize_step_info() {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(2), Custom(GameInput::Jumping(true))).unwrap();
+        participant_steps.push(ParticipantId(5), Step::Forced).unwrap();
+        participant_steps.push(ParticipantId(7), Custom(GameInput::MoveHorizontal(1024))).unwrap();
+        let step_info = StepInfo {
+            step: participant_steps,
+            tick_id: TickId(0x01020304),
+        };
+
+        let mut buffer = Vec::new();
+        step_info.serialize(&mut buffer);
+        assert_eq!(&buffer[..4], &[0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(StepInfo::<GameInput>::deserialize(&buffer), Ok((step_info, buffer.len())));
+    }
+
+    #[test]
+    fn deserialize_back_to_back() {
+        let mut first = ParticipantSteps::new();
+        first.push(ParticipantId(1), Custom(GameInput::MoveHorizontal(-7))).unwrap();
+        first.push(ParticipantId(3), Step::WaitingForReconnect).unwrap();
+        let mut second = ParticipantSteps::new();
+        second.push(ParticipantId(1), Custom(GameInput::Jumping(false))).unwrap();
+
+        let mut buffer = Vec::new();
+        first.serialize(&mut buffer);
+        second.serialize(&mut buffer);
+
+        let (decoded_first, first_octet_count) = ParticipantSteps::<GameInput>::deserialize(&buffer).unwrap();
+        let (decoded_second, second_octet_count) =
+            ParticipantSteps::<GameInput>::deserialize(&buffer[first_octet_count..]).unwrap();
+        assert_eq!(decoded_first, first);
+        assert_eq!(decoded_second, second);
+        assert_eq!(first_octet_count + second_octet_count, buffer.len());
+    }
+
+    #[test]
+    fn deserialize_truncated() {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(4), Custom(GameInput::MoveHorizontal(300))).unwrap();
+        participant_steps.push(ParticipantId(9), Step::Forced).unwrap();
+        let step_info = StepInfo {
+            step: participant_steps,
+            tick_id: TickId(42),
+        };
+        let mut buffer = Vec::new();
+        step_info.serialize(&mut buffer);
+
+        for length in 0..buffer.len() {
+            assert!(matches!(
+                StepInfo::<GameInput>::deserialize(&buffer[..length]),
+                Err(DecodeError::UnexpectedEnd { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn deserialize_unknown_step_type() {
+        assert_eq!(
+            Step::<GameInput>::deserialize(&[0xff]),
+            Err(DecodeError::UnknownStepType(0xff))
+        );
+    }
 }
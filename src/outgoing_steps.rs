@@ -0,0 +1,198 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use tick_id::TickId;
+
+use crate::ack::ReceiveMask;
+use crate::sequence::TickSequence;
+use crate::steps_range::{steps_range_header, StepsRange, STEPS_RANGE_HEADER_OCTET_COUNT, STEPS_RANGE_MAX_COUNT};
+use crate::{ParticipantSteps, Serialize, Steps};
+
+/// Steps that are sent to a receiver and repeated in every packet until the receiver has acknowledged them.
+pub struct OutgoingSteps<T> {
+    steps: Steps<T>,
+    last_acknowledged_tick_id: Option<TickId>,
+}
+
+impl<T> OutgoingSteps<T> {
+    pub fn new(initial_tick_id: TickId) -> Self {
+        Self {
+            steps: Steps::new_with_initial_tick(initial_tick_id),
+            last_acknowledged_tick_id: None,
+        }
+    }
+
+    pub fn push(&mut self, step: ParticipantSteps<T>) {
+        self.steps.push(step);
+    }
+
+    /// Discards every step up to and including `tick_id`. Acknowledgements older than a previous one are ignored.
+    pub fn acknowledge(&mut self, tick_id: TickId) {
+        if self
+            .last_acknowledged_tick_id
+            .is_some_and(|last_acknowledged_tick_id| !last_acknowledged_tick_id.is_before(tick_id))
+        {
+            return;
+        }
+        self.last_acknowledged_tick_id = Some(tick_id);
+        self.steps.pop_up_to(tick_id.wrapping_add(1));
+    }
+
+    /// Discards every step before the first tick that the receiver is still missing.
+    pub fn acknowledge_mask(&mut self, mask: &ReceiveMask) {
+        self.acknowledge(mask.first_missing_tick_id().wrapping_sub(1));
+    }
+
+    pub fn last_acknowledged_tick_id(&self) -> Option<TickId> {
+        self.last_acknowledged_tick_id
+    }
+
+    /// The oldest steps that have not been acknowledged, at most `max_count` of them.
+    pub fn unacknowledged_range(&self, max_count: usize) -> StepsRange<&ParticipantSteps<T>> {
+        self.steps.steps_range(self.first_unacknowledged_tick_id(), max_count)
+    }
+
+    /// Writes the oldest unacknowledged steps as a [`StepsRange`], limited to `max_count` steps and to
+    /// `max_octets` octets including the range header. Returns the number of steps written.
+    /// Nothing is written if not even the first step fits.
+    pub fn serialize_unacknowledged(&self, max_count: usize, max_octets: usize, buffer: &mut Vec<u8>) -> usize
+    where
+        T: Serialize,
+    {
+        if max_octets < STEPS_RANGE_HEADER_OCTET_COUNT {
+            return 0;
+        }
+        let range = self.unacknowledged_range(max_count.min(STEPS_RANGE_MAX_COUNT));
+        let start_position = buffer.len();
+        buffer.extend_from_slice(&steps_range_header(range.start_tick_id, 0));
+
+        let mut count = 0;
+        for step in range.steps {
+            let position_before_step = buffer.len();
+            step.serialize(buffer);
+            if buffer.len() - start_position > max_octets {
+                buffer.truncate(position_before_step);
+                break;
+            }
+            count += 1;
+        }
+        if count == 0 {
+            buffer.truncate(start_position);
+            return 0;
+        }
+        buffer[start_position..start_position + STEPS_RANGE_HEADER_OCTET_COUNT]
+            .copy_from_slice(&steps_range_header(range.start_tick_id, count));
+
+        count
+    }
+
+    pub fn steps(&self) -> &Steps<T> {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    fn first_unacknowledged_tick_id(&self) -> TickId {
+        self.steps.front_tick_id().unwrap_or(self.steps.expected_write_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        read_octets, DecodeError, Deserialize, ParticipantId, Step, PARTICIPANT_ID_OCTET_COUNT,
+        PARTICIPANT_STEPS_COUNT_OCTET_COUNT,
+    };
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Aim(u8);
+
+    impl Serialize for Aim {
+        fn serialize(&self, buffer: &mut Vec<u8>) {
+            buffer.push(self.0);
+        }
+    }
+
+    impl Deserialize for Aim {
+        fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+            let [angle] = read_octets(bytes)?;
+            Ok((Aim(angle), 1))
+        }
+    }
+
+    fn outgoing(start: u32, count: u8) -> OutgoingSteps<Aim> {
+        let mut outgoing = OutgoingSteps::new(TickId(start));
+        for angle in 0..count {
+            let mut participant_steps = ParticipantSteps::new();
+            participant_steps.push(ParticipantId(0), Step::Custom(Aim(angle))).unwrap();
+            outgoing.push(participant_steps);
+        }
+        outgoing
+    }
+
+    #[test]
+    fn acknowledge_discards_steps() {
+        let mut outgoing = outgoing(10, 6);
+        assert_eq!(outgoing.unacknowledged_range(100).len(), 6);
+
+        outgoing.acknowledge(TickId(12));
+        assert_eq!(outgoing.last_acknowledged_tick_id(), Some(TickId(12)));
+        let range = outgoing.unacknowledged_range(2);
+        assert_eq!(range.start_tick_id, TickId(13));
+        assert_eq!(range.len(), 2);
+
+        outgoing.acknowledge(TickId(11));
+        assert_eq!(outgoing.last_acknowledged_tick_id(), Some(TickId(12)));
+        assert_eq!(outgoing.len(), 3);
+    }
+
+    #[test]
+    fn acknowledge_with_mask() {
+        let mut outgoing = outgoing(10, 6);
+        outgoing.acknowledge_mask(&ReceiveMask::new(TickId(10), 0b1011));
+        assert_eq!(outgoing.steps().front_tick_id(), Some(TickId(12)));
+
+        outgoing.acknowledge_mask(&ReceiveMask::new(TickId(16), 0));
+        assert!(outgoing.is_empty());
+        assert_eq!(outgoing.unacknowledged_range(8).start_tick_id, TickId(16));
+    }
+
+    #[test]
+    fn serialize_within_octet_budget() {
+        let outgoing = outgoing(10, 6);
+        // Header is 6 octets and each step is the count, participant id, step type and angle.
+        let step_octet_count = PARTICIPANT_STEPS_COUNT_OCTET_COUNT + PARTICIPANT_ID_OCTET_COUNT + 2;
+        let mut buffer = Vec::new();
+        assert_eq!(
+            outgoing.serialize_unacknowledged(10, 6 + step_octet_count * 3 + 2, &mut buffer),
+            3
+        );
+        assert_eq!(buffer.len(), 6 + step_octet_count * 3);
+
+        let (range, _) = StepsRange::<ParticipantSteps<Aim>>::deserialize(&buffer).unwrap();
+        assert_eq!(range.start_tick_id, TickId(10));
+        assert_eq!(range.len(), 3);
+        assert_eq!(range.steps[2].get(ParticipantId(0)), Some(&Step::Custom(Aim(2))));
+
+        let mut buffer = Vec::new();
+        assert_eq!(outgoing.serialize_unacknowledged(2, 1000, &mut buffer), 2);
+    }
+
+    #[test]
+    fn serialize_nothing_when_no_step_fits() {
+        let outgoing = outgoing(10, 6);
+        let mut buffer = vec![0xff];
+        assert_eq!(outgoing.serialize_unacknowledged(10, 4, &mut buffer), 0);
+        assert_eq!(outgoing.serialize_unacknowledged(10, 7, &mut buffer), 0);
+        assert_eq!(buffer, vec![0xff]);
+    }
+}
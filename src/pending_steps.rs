@@ -2,69 +2,218 @@
  *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
  *  Licensed under the MIT License. See LICENSE in the project root for license information.
  *--------------------------------------------------------------------------------------------------------*/
-use discoid::discoid::DiscoidBuffer;
+use std::collections::VecDeque;
 
-use crate::{Step, TickId};
+use crate::ack::{MissingRanges, ReceiveMask, TickRange, RECEIVE_MASK_TICK_COUNT};
+use crate::sequence::TickSequence;
+use crate::{ParticipantId, ParticipantSteps, Step, Steps, StepsError, TickId};
 
 pub struct PendingStepInfo<T> {
     pub step: Step<T>,
     pub tick_id: TickId,
 }
 
+/// What happened to a step handed to [`PendingSteps::set`].
+///
+/// Late and duplicate arrivals are expected on lossy links with retransmissions, so they are not errors.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SetOutcome {
+    Accepted,
+    /// The tick is older than the window and has already been consumed.
+    IgnoredStale,
+    /// The tick is already held in the window.
+    IgnoredDuplicate,
+}
+
 pub struct PendingSteps<T> {
-    steps: DiscoidBuffer<PendingStepInfo<T>>,
+    steps: VecDeque<Option<PendingStepInfo<T>>>,
     front_tick_id: TickId,
     capacity: usize,
 }
 
 impl<T> PendingSteps<T> {
     pub fn new(window_size: usize, tick_id: TickId) -> Self {
+        assert!(window_size > 0, "pending steps window must have room for at least one tick");
         Self {
-            steps: DiscoidBuffer::new(window_size),
+            steps: (0..window_size).map(|_| None).collect(),
             front_tick_id: tick_id,
             capacity: window_size,
         }
     }
 
-    pub fn set(&mut self, tick_id: TickId, step: Step<T>) -> Result<(), String> {
-        let index_in_discoid = tick_id.value() - self.front_tick_id.value();
-        if index_in_discoid >= self.capacity as u32 { // self.steps.capacity()
-            return Err("pending_steps: out of scope".to_string());
+    pub fn set(&mut self, tick_id: TickId, step: Step<T>) -> Result<SetOutcome, StepsError> {
+        let index_in_window = tick_id.wrapping_diff(self.front_tick_id);
+        if index_in_window < 0 {
+            return Ok(SetOutcome::IgnoredStale);
+        }
+        if index_in_window >= self.capacity as i64 {
+            return Err(StepsError::TickTooFarAhead {
+                tick_id,
+                max_tick_id: self.front_tick_id.wrapping_add(self.capacity as u32 - 1),
+            });
+        }
+        let slot = &mut self.steps[index_in_window as usize];
+        if slot.is_some() {
+            return Ok(SetOutcome::IgnoredDuplicate);
         }
 
-        self.steps.set_at_index(index_in_discoid as usize, PendingStepInfo::<T> {
-            step,
-            tick_id,
-        });
-        Ok(())
+        *slot = Some(PendingStepInfo::<T> { step, tick_id });
+        Ok(SetOutcome::Accepted)
+    }
+
+    /// Like [`PendingSteps::set`], but a tick that is already held with a different step is reported as
+    /// [`StepsError::ConflictingDuplicate`].
+    pub fn set_checked(&mut self, tick_id: TickId, step: Step<T>) -> Result<SetOutcome, StepsError>
+    where
+        T: PartialEq,
+    {
+        if self.get(tick_id).is_some_and(|stored| stored.step != step) {
+            return Err(StepsError::ConflictingDuplicate(tick_id));
+        }
+        self.set(tick_id, step)
+    }
+
+    /// The step held for `tick_id`, if it is within the window and has been received.
+    pub fn get(&self, tick_id: TickId) -> Option<&PendingStepInfo<T>> {
+        let index_in_window = tick_id.wrapping_diff(self.front_tick_id);
+        if index_in_window < 0 {
+            return None;
+        }
+        self.steps.get(index_in_window as usize).and_then(Option::as_ref)
     }
 
     pub fn discard_up_to(&mut self, tick_id: TickId) {
-        let count_in_discoid = tick_id - self.front_tick_id;
-        if count_in_discoid < 0 {
+        let count_in_window = tick_id.wrapping_diff(self.front_tick_id);
+        if count_in_window <= 0 {
             return;
         }
-        self.steps.discard_front(count_in_discoid as usize);
+        for _ in 0..(count_in_window as usize).min(self.capacity) {
+            self.steps.pop_front();
+            self.steps.push_back(None);
+        }
+        self.front_tick_id = tick_id;
     }
 
     pub fn is_empty(&self) -> bool {
-        self.steps.get_at_index(0).is_none()
+        self.peek().is_none()
+    }
+
+    /// Removes the step at the front of the window, if it has been received, and advances the window by one tick.
+    pub fn pop(&mut self) -> Option<PendingStepInfo<T>> {
+        let info = self.steps.front_mut()?.take()?;
+        self.steps.pop_front();
+        self.steps.push_back(None);
+        self.front_tick_id = self.front_tick_id.wrapping_add(1);
+        Some(info)
     }
 
-    pub fn pop(&mut self) -> &PendingStepInfo<T> {
-        let value = self.steps.get_at_index(0).unwrap();
-        //self.steps.discard_front(1);
-        value
+    pub fn peek(&self) -> Option<&PendingStepInfo<T>> {
+        self.steps.front().and_then(Option::as_ref)
     }
 
     pub fn front_tick_id(&self) -> Option<TickId> {
-        self.steps.get_at_index(0).map(|info| info.tick_id)
+        self.peek().map(|info| info.tick_id)
+    }
+
+    pub fn window_front_tick_id(&self) -> TickId {
+        self.front_tick_id
+    }
+
+    /// Which of the first [`RECEIVE_MASK_TICK_COUNT`] ticks in the window have been received.
+    pub fn receive_mask(&self) -> ReceiveMask {
+        let bits = self
+            .steps
+            .iter()
+            .take(RECEIVE_MASK_TICK_COUNT)
+            .enumerate()
+            .filter(|(_, slot)| slot.is_some())
+            .fold(0u64, |bits, (index, _)| bits | (1 << index));
+
+        ReceiveMask::new(self.front_tick_id, bits)
+    }
+
+    /// The ticks between the window front and the newest received tick that have not been received yet.
+    pub fn missing_ranges(&self) -> MissingRanges {
+        let Some(last_received_index) = self.steps.iter().rposition(Option::is_some) else {
+            return MissingRanges::default();
+        };
+
+        let mut ranges = Vec::new();
+        let mut missing_start: Option<usize> = None;
+        for (index, slot) in self.steps.iter().enumerate().take(last_received_index) {
+            match (slot, missing_start) {
+                (None, None) => missing_start = Some(index),
+                (Some(_), Some(start)) => {
+                    ranges.extend(TickRange::split(self.front_tick_id.wrapping_add(start as u32), index - start));
+                    missing_start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(start) = missing_start {
+            ranges.extend(TickRange::split(
+                self.front_tick_id.wrapping_add(start as u32),
+                last_received_index - start,
+            ));
+        }
+
+        MissingRanges::new(ranges)
+    }
+
+    /// Pops the longest run of consecutive received steps, starting at the front of the window.
+    pub fn drain_ready(&mut self) -> DrainReady<'_, T> {
+        DrainReady {
+            pending_steps: self,
+        }
+    }
+
+    /// Moves all ready steps into `steps`, each as the only step of `participant_id` for that tick.
+    ///
+    /// The window front must be the next tick that `steps` expects, so that the ticks stay contiguous.
+    /// Returns the number of ticks that were moved.
+    pub fn drain_ready_into(&mut self, participant_id: ParticipantId, steps: &mut Steps<T>) -> Result<usize, StepsError> {
+        let tick_difference = self.front_tick_id.wrapping_diff(steps.expected_write_id);
+        if tick_difference > 0 {
+            return Err(StepsError::TickTooFarAhead {
+                tick_id: self.front_tick_id,
+                max_tick_id: steps.expected_write_id,
+            });
+        }
+        if tick_difference < 0 {
+            return Err(StepsError::TickTooOld {
+                tick_id: self.front_tick_id,
+                min_tick_id: steps.expected_write_id,
+            });
+        }
+
+        let mut count = 0;
+        for info in self.drain_ready() {
+            let mut participant_steps = ParticipantSteps::new();
+            participant_steps.push(participant_id, info.step)?;
+            steps.push(participant_steps);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+pub struct DrainReady<'a, T> {
+    pending_steps: &'a mut PendingSteps<T>,
+}
+
+impl<T> Iterator for DrainReady<'_, T> {
+    type Item = PendingStepInfo<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pending_steps.pop()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::Step::Custom;
+    use crate::{Deserialize, Serialize};
 
     use super::*;
 
@@ -80,15 +229,307 @@ mod tests {
         let first_tick_id = TickId(12);
         steps.set(first_tick_id, Custom(GameInput::MoveHorizontal(-2))).expect("this should work");
         assert_eq!(steps.front_tick_id(), None);
-        assert_eq!(steps.is_empty(), true);
+        assert!(steps.is_empty());
         steps.set(first_tick_id - 2, Custom(GameInput::Jumping(false))).expect("this should work");
-        assert_eq!(steps.is_empty(), false);
-        let first_jumping_step = steps.pop();
+        assert!(!steps.is_empty());
+        let first_jumping_step = steps.peek().unwrap();
         assert_eq!(first_jumping_step.tick_id, first_tick_id - 2);
         assert_eq!(steps.front_tick_id().unwrap().value(), 10);
         steps.discard_up_to(first_tick_id);
-        assert_eq!(steps.is_empty(), false);
+        assert!(!steps.is_empty());
         steps.discard_up_to(first_tick_id + 1);
-        assert_eq!(steps.is_empty(), true);
+        assert!(steps.is_empty());
+    }
+
+    #[test]
+    fn set_out_of_window() {
+        let mut steps = PendingSteps::<GameInput>::new(4, TickId(10));
+        assert_eq!(
+            steps.set(TickId(9), Custom(GameInput::Jumping(true))),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        assert_eq!(
+            steps.set(TickId(14), Custom(GameInput::Jumping(true))),
+            Err(StepsError::TickTooFarAhead {
+                tick_id: TickId(14),
+                max_tick_id: TickId(13)
+            })
+        );
+        assert_eq!(
+            steps.set(TickId(13), Custom(GameInput::Jumping(true))),
+            Ok(SetOutcome::Accepted)
+        );
+        assert_eq!(
+            steps.set(TickId(13), Custom(GameInput::Jumping(false))),
+            Ok(SetOutcome::IgnoredDuplicate)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one tick")]
+    fn empty_window_is_rejected() {
+        PendingSteps::<GameInput>::new(0, TickId(0));
+    }
+
+    #[test]
+    fn missing_ranges_in_large_window() {
+        let mut steps = PendingSteps::<GameInput>::new(200_000, TickId(0));
+        steps.set(TickId(70_000), Custom(GameInput::Jumping(true))).unwrap();
+        steps.set(TickId(199_999), Custom(GameInput::Jumping(true))).unwrap();
+
+        let missing = steps.missing_ranges();
+        assert_eq!(missing.tick_count(), 199_998);
+        assert!(missing.ranges.iter().all(|range| range.count > 0));
+        assert_eq!(missing.ranges[1], TickRange::new(TickId(u16::MAX as u32), (70_000 - u16::MAX as u32) as u16));
+        assert_eq!(missing.ranges[2].start_tick_id, TickId(70_001));
+        let mut buffer = Vec::new();
+        missing.serialize(&mut buffer);
+        assert_eq!(MissingRanges::deserialize(&buffer).unwrap().0, missing);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn set_checked_detects_conflicting_step() {
+        let mut steps = PendingSteps::<GameInput>::new(4, TickId(10));
+        assert_eq!(
+            steps.set_checked(TickId(11), Custom(GameInput::Jumping(true))),
+            Ok(SetOutcome::Accepted)
+        );
+        assert_eq!(
+            steps.set_checked(TickId(11), Custom(GameInput::Jumping(true))),
+            Ok(SetOutcome::IgnoredDuplicate)
+        );
+        assert_eq!(
+            steps.set_checked(TickId(11), Custom(GameInput::Jumping(false))),
+            Err(StepsError::ConflictingDuplicate(TickId(11)))
+        );
+        assert_eq!(steps.get(TickId(11)).unwrap().step, Custom(GameInput::Jumping(true)));
+        assert_eq!(
+            steps.set_checked(TickId(9), Custom(GameInput::Jumping(false))),
+            Ok(SetOutcome::IgnoredStale)
+        );
+    }
+
+    #[test]
+    fn late_retransmission_is_ignored() {
+        let mut steps = PendingSteps::<GameInput>::new(8, TickId(1000));
+        for tick in [0, 999, 1, 500] {
+            assert_eq!(
+                steps.set(TickId(tick), Custom(GameInput::Jumping(true))),
+                Ok(SetOutcome::IgnoredStale)
+            );
+        }
+        assert!(steps.is_empty());
+        assert_eq!(steps.window_front_tick_id(), TickId(1000));
+    }
+
+    #[test]
+    fn discard_advances_window() {
+        let mut steps = PendingSteps::<GameInput>::new(4, TickId(10));
+        steps.discard_up_to(TickId(12));
+        assert_eq!(steps.window_front_tick_id(), TickId(12));
+        assert_eq!(steps.set(TickId(15), Custom(GameInput::Jumping(true))), Ok(SetOutcome::Accepted));
+        steps.discard_up_to(TickId(11));
+        assert_eq!(steps.window_front_tick_id(), TickId(12));
+        steps.discard_up_to(TickId(15));
+        assert_eq!(steps.front_tick_id(), Some(TickId(15)));
+        steps.discard_up_to(TickId(100));
+        assert!(steps.is_empty());
+        assert_eq!(steps.set(TickId(103), Custom(GameInput::Jumping(false))), Ok(SetOutcome::Accepted));
+        assert!(steps.set(TickId(104), Custom(GameInput::Jumping(false))).is_err());
+    }
+
+    #[test]
+    fn set_and_discard_cycles() {
+        for window_size in 1..=33 {
+            let mut steps = PendingSteps::<GameInput>::new(window_size, TickId(7));
+            let mut seed = window_size as u32;
+            let mut next_tick = 7;
+            while next_tick < 2000 {
+                // Fill the whole window in a scrambled order, with some duplicates.
+                let mut offsets: Vec<u32> = (0..window_size as u32).collect();
+                for index in (1..offsets.len()).rev() {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    offsets.swap(index, (seed >> 16) as usize % (index + 1));
+                }
+                for offset in offsets {
+                    let tick_id = TickId(next_tick + offset);
+                    let value = tick_id.value() as i32;
+                    assert_eq!(
+                        steps.set(tick_id, Custom(GameInput::MoveHorizontal(value))),
+                        Ok(SetOutcome::Accepted)
+                    );
+                    assert_eq!(
+                        steps.set(tick_id, Custom(GameInput::MoveHorizontal(value))),
+                        Ok(SetOutcome::IgnoredDuplicate)
+                    );
+                }
+
+                let discard_count = 1 + (seed >> 8) % window_size as u32;
+                for tick in next_tick..next_tick + discard_count {
+                    assert_eq!(steps.front_tick_id(), Some(TickId(tick)));
+                    assert_eq!(steps.pop().unwrap().step, Custom(GameInput::MoveHorizontal(tick as i32)));
+                }
+                next_tick += discard_count;
+                assert_eq!(steps.window_front_tick_id(), TickId(next_tick));
+                assert_eq!(
+                    steps.set(TickId(next_tick - 1), Custom(GameInput::Jumping(true))),
+                    Ok(SetOutcome::IgnoredStale)
+                );
+
+                // Whatever was not discarded is still in place.
+                for tick in next_tick..next_tick + window_size as u32 - discard_count {
+                    assert_eq!(
+                        steps.set(TickId(tick), Custom(GameInput::Jumping(true))),
+                        Ok(SetOutcome::IgnoredDuplicate)
+                    );
+                }
+                steps.discard_up_to(TickId(next_tick + window_size as u32 - discard_count));
+                next_tick += window_size as u32 - discard_count;
+                assert!(steps.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn pop_and_peek() {
+        let mut steps = PendingSteps::<GameInput>::new(4, TickId(20));
+        assert!(steps.pop().is_none());
+        steps.set(TickId(21), Custom(GameInput::Jumping(true))).unwrap();
+        assert!(steps.peek().is_none());
+        assert!(steps.pop().is_none());
+        assert_eq!(steps.window_front_tick_id(), TickId(20));
+
+        steps.set(TickId(20), Custom(GameInput::MoveHorizontal(3))).unwrap();
+        assert_eq!(steps.peek().unwrap().tick_id, TickId(20));
+        let first = steps.pop().unwrap();
+        assert_eq!(first.tick_id, TickId(20));
+        assert_eq!(first.step, Custom(GameInput::MoveHorizontal(3)));
+        assert_eq!(steps.window_front_tick_id(), TickId(21));
+
+        let second = steps.pop().unwrap();
+        assert_eq!(second.tick_id, TickId(21));
+        assert!(steps.pop().is_none());
+        assert_eq!(steps.window_front_tick_id(), TickId(22));
+        assert_eq!(steps.set(TickId(25), Custom(GameInput::Jumping(false))), Ok(SetOutcome::Accepted));
+    }
+
+    #[test]
+    fn drain_contiguous_prefix() {
+        let mut steps = PendingSteps::<GameInput>::new(8, TickId(30));
+        for tick in [31, 33, 30, 32, 35] {
+            steps.set(TickId(tick), Custom(GameInput::MoveHorizontal(tick as i32))).unwrap();
+        }
+
+        let drained: Vec<u32> = steps.drain_ready().map(|info| info.tick_id.value()).collect();
+        assert_eq!(drained, vec![30, 31, 32, 33]);
+        assert_eq!(steps.window_front_tick_id(), TickId(34));
+        assert_eq!(steps.drain_ready().count(), 0);
+
+        steps.set(TickId(34), Custom(GameInput::Jumping(true))).unwrap();
+        let drained: Vec<u32> = steps.drain_ready().map(|info| info.tick_id.value()).collect();
+        assert_eq!(drained, vec![34, 35]);
+    }
+
+    #[test]
+    fn drain_into_steps() {
+        let mut pending_steps = PendingSteps::<GameInput>::new(8, TickId(30));
+        let mut steps = Steps::new_with_initial_tick(TickId(30));
+        pending_steps.set(TickId(31), Custom(GameInput::Jumping(true))).unwrap();
+        assert_eq!(pending_steps.drain_ready_into(ParticipantId(3), &mut steps), Ok(0));
+
+        pending_steps.set(TickId(30), Custom(GameInput::Jumping(false))).unwrap();
+        pending_steps.set(TickId(33), Custom(GameInput::Jumping(false))).unwrap();
+        assert_eq!(pending_steps.drain_ready_into(ParticipantId(3), &mut steps), Ok(2));
+        assert_eq!(steps.front_tick_id(), Some(TickId(30)));
+        assert_eq!(steps.back_tick_id(), Some(TickId(31)));
+        let first = steps.pop().unwrap().unwrap();
+        assert_eq!(first.step.len(), 1);
+        assert_eq!(first.step.get(ParticipantId(3)), Some(&Custom(GameInput::Jumping(false))));
+
+        let mut other_steps = Steps::new_with_initial_tick(TickId(40));
+        assert_eq!(
+            pending_steps.drain_ready_into(ParticipantId(3), &mut other_steps),
+            Err(StepsError::TickTooOld {
+                tick_id: TickId(32),
+                min_tick_id: TickId(40)
+            })
+        );
+    }
+
+    #[test]
+    fn receive_mask_follows_window() {
+        let mut steps = PendingSteps::<GameInput>::new(80, TickId(200));
+        assert_eq!(steps.receive_mask(), ReceiveMask::new(TickId(200), 0));
+
+        for tick in [200, 201, 204, 263, 264] {
+            steps.set(TickId(tick), Custom(GameInput::Jumping(true))).unwrap();
+        }
+        assert_eq!(steps.receive_mask(), ReceiveMask::new(TickId(200), 0b10011 | 1 << 63));
+
+        steps.drain_ready().count();
+        assert_eq!(steps.receive_mask(), ReceiveMask::new(TickId(202), 0b100 | 1 << 61 | 1 << 62));
+    }
+
+    #[test]
+    fn missing_ranges_up_to_newest() {
+        let mut steps = PendingSteps::<GameInput>::new(16, TickId(100));
+        assert!(steps.missing_ranges().is_empty());
+
+        for tick in [100, 101, 102, 103, 104, 107] {
+            steps.set(TickId(tick), Custom(GameInput::Jumping(true))).unwrap();
+        }
+        assert_eq!(
+            steps.missing_ranges(),
+            MissingRanges::new(vec![TickRange::new(TickId(105), 2)])
+        );
+
+        steps.drain_ready().count();
+        steps.set(TickId(110), Custom(GameInput::Jumping(true))).unwrap();
+        steps.set(TickId(113), Custom(GameInput::Jumping(true))).unwrap();
+        assert_eq!(
+            steps.missing_ranges(),
+            MissingRanges::new(vec![
+                TickRange::new(TickId(105), 2),
+                TickRange::new(TickId(108), 2),
+                TickRange::new(TickId(111), 2),
+            ])
+        );
+    }
+
+    #[test]
+    fn window_across_tick_wrap_around() {
+        let mut steps = PendingSteps::<GameInput>::new(8, TickId(u32::MAX - 2));
+        for tick in [u32::MAX - 2, u32::MAX, 0, 2] {
+            assert_eq!(
+                steps.set(TickId(tick), Custom(GameInput::Jumping(true))),
+                Ok(SetOutcome::Accepted)
+            );
+        }
+        assert_eq!(
+            steps.set(TickId(u32::MAX - 3), Custom(GameInput::Jumping(true))),
+            Ok(SetOutcome::IgnoredStale)
+        );
+        assert_eq!(
+            steps.set(TickId(5), Custom(GameInput::Jumping(true))),
+            Err(StepsError::TickTooFarAhead {
+                tick_id: TickId(5),
+                max_tick_id: TickId(4)
+            })
+        );
+        assert_eq!(steps.receive_mask(), ReceiveMask::new(TickId(u32::MAX - 2), 0b101101));
+        assert_eq!(
+            steps.missing_ranges(),
+            MissingRanges::new(vec![TickRange::new(TickId(u32::MAX - 1), 1), TickRange::new(TickId(1), 1)])
+        );
+
+        steps.set(TickId(u32::MAX - 1), Custom(GameInput::Jumping(false))).unwrap();
+        let drained: Vec<u32> = steps.drain_ready().map(|info| info.tick_id.value()).collect();
+        assert_eq!(drained, vec![u32::MAX - 2, u32::MAX - 1, u32::MAX, 0]);
+        assert_eq!(steps.window_front_tick_id(), TickId(1));
+
+        steps.discard_up_to(TickId(2));
+        assert_eq!(steps.front_tick_id(), Some(TickId(2)));
+        steps.discard_up_to(TickId(u32::MAX));
+        assert_eq!(steps.window_front_tick_id(), TickId(2));
+    }
+}
@@ -0,0 +1,184 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use tick_id::TickId;
+
+use crate::sequence::TickSequence;
+use crate::{ParticipantSteps, StepInfo, Steps, StepsError};
+
+/// Compares locally predicted steps with the authoritative steps from the host.
+///
+/// Every prediction for a tick that has been authoritatively decided is dropped, and the first tick where a
+/// prediction did not match is remembered until it is taken, so that the simulation can roll back to it.
+pub struct StepReconciler<T> {
+    predicted: Steps<T>,
+    authoritative: Steps<T>,
+    first_misprediction: Option<TickId>,
+}
+
+impl<T: PartialEq> StepReconciler<T> {
+    pub fn new(initial_tick_id: TickId) -> Self {
+        Self {
+            predicted: Steps::new_with_initial_tick(initial_tick_id),
+            authoritative: Steps::new_with_initial_tick(initial_tick_id),
+            first_misprediction: None,
+        }
+    }
+
+    pub fn push_predicted(&mut self, step: ParticipantSteps<T>) {
+        self.predicted.push(step);
+    }
+
+    /// Adds the next authoritative tick and checks it against the prediction for the same tick.
+    /// Returns `true` if the prediction was wrong.
+    pub fn push_authoritative(&mut self, step: ParticipantSteps<T>) -> bool {
+        let tick_id = self.authoritative.expected_write_id;
+        let mispredicted = self
+            .predicted
+            .get(tick_id)
+            .is_some_and(|predicted| !is_confirmed(&predicted.step, &step));
+        if mispredicted && self.first_misprediction.is_none() {
+            self.first_misprediction = Some(tick_id);
+        }
+
+        self.authoritative.push(step);
+        self.predicted.pop_up_to(tick_id.wrapping_add(1));
+        if self.predicted.expected_write_id.is_before(self.authoritative.expected_write_id) {
+            // The host got ahead of the predictions, so the next prediction is for the first undecided tick.
+            self.predicted = Steps::new_with_initial_tick(self.authoritative.expected_write_id);
+        }
+
+        mispredicted
+    }
+
+    /// Drops the predictions from `tick_id` onward, so that re-simulated predictions can be pushed after a
+    /// rollback. Ticks that are already authoritatively decided have no predictions left to drop.
+    /// Returns the number of dropped predictions.
+    pub fn truncate_predicted_from(&mut self, tick_id: TickId) -> usize {
+        let first_undecided_tick_id = self.authoritative.expected_write_id;
+        let tick_id = if tick_id.is_before(first_undecided_tick_id) {
+            first_undecided_tick_id
+        } else {
+            tick_id
+        };
+        self.predicted.truncate_from(tick_id)
+    }
+
+    /// The oldest tick where a prediction differed from the authoritative step since the last call.
+    pub fn take_first_misprediction(&mut self) -> Option<TickId> {
+        self.first_misprediction.take()
+    }
+
+    pub fn pop_authoritative(&mut self) -> Result<Option<StepInfo<T>>, StepsError> {
+        self.authoritative.pop()
+    }
+
+    pub fn predicted(&self) -> &Steps<T> {
+        &self.predicted
+    }
+
+    pub fn authoritative(&self) -> &Steps<T> {
+        &self.authoritative
+    }
+}
+
+/// Every predicted participant step must have an equal authoritative step for the same participant.
+fn is_confirmed<T: PartialEq>(predicted: &ParticipantSteps<T>, authoritative: &ParticipantSteps<T>) -> bool {
+    predicted
+        .iter()
+        .all(|predicted_step| authoritative.get(predicted_step.participant_id) == Some(&predicted_step.step))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ParticipantId, Step};
+    use crate::Step::Custom;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum GameInput {
+        Idle,
+        Fire,
+    }
+
+    fn local(step: Step<GameInput>) -> ParticipantSteps<GameInput> {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(1), step).unwrap();
+        participant_steps
+    }
+
+    fn combined(local_step: Step<GameInput>) -> ParticipantSteps<GameInput> {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(0), Custom(GameInput::Idle)).unwrap();
+        participant_steps.push(ParticipantId(1), local_step).unwrap();
+        participant_steps
+    }
+
+    #[test]
+    fn confirmed_predictions_are_dropped() {
+        let mut reconciler = StepReconciler::new(TickId(5));
+        for _ in 0..4 {
+            reconciler.push_predicted(local(Custom(GameInput::Idle)));
+        }
+
+        assert!(!reconciler.push_authoritative(combined(Custom(GameInput::Idle))));
+        assert!(!reconciler.push_authoritative(combined(Custom(GameInput::Idle))));
+        assert_eq!(reconciler.predicted().front_tick_id(), Some(TickId(7)));
+        assert_eq!(reconciler.authoritative().len(), 2);
+        assert_eq!(reconciler.take_first_misprediction(), None);
+        assert_eq!(reconciler.pop_authoritative().unwrap().unwrap().tick_id, TickId(5));
+    }
+
+    #[test]
+    fn first_misprediction_is_reported() {
+        let mut reconciler = StepReconciler::new(TickId(5));
+        for _ in 0..4 {
+            reconciler.push_predicted(local(Custom(GameInput::Idle)));
+        }
+
+        assert!(!reconciler.push_authoritative(combined(Custom(GameInput::Idle))));
+        assert!(reconciler.push_authoritative(combined(Custom(GameInput::Fire))));
+        assert!(reconciler.push_authoritative(combined(Step::Forced)));
+        assert_eq!(reconciler.predicted().len(), 1);
+        assert_eq!(reconciler.take_first_misprediction(), Some(TickId(6)));
+        assert_eq!(reconciler.take_first_misprediction(), None);
+
+        assert!(!reconciler.push_authoritative(combined(Custom(GameInput::Idle))));
+        assert!(reconciler.predicted().is_empty());
+        assert!(!reconciler.push_authoritative(combined(Custom(GameInput::Fire))));
+        assert_eq!(reconciler.take_first_misprediction(), None);
+    }
+
+    #[test]
+    fn predictions_are_replaced_after_rollback() {
+        let mut reconciler = StepReconciler::new(TickId(5));
+        for _ in 0..4 {
+            reconciler.push_predicted(local(Custom(GameInput::Idle)));
+        }
+        assert!(reconciler.push_authoritative(combined(Custom(GameInput::Fire))));
+        let rollback_tick_id = reconciler.take_first_misprediction().unwrap();
+
+        assert_eq!(reconciler.truncate_predicted_from(rollback_tick_id), 3);
+        assert!(reconciler.predicted().is_empty());
+        reconciler.push_predicted(local(Custom(GameInput::Fire)));
+        assert_eq!(reconciler.predicted().front_tick_id(), Some(TickId(6)));
+        assert!(!reconciler.push_authoritative(combined(Custom(GameInput::Fire))));
+    }
+
+    #[test]
+    fn predictions_catch_up_with_authoritative() {
+        let mut reconciler = StepReconciler::new(TickId(5));
+        reconciler.push_predicted(local(Custom(GameInput::Idle)));
+        for _ in 0..3 {
+            reconciler.push_authoritative(combined(Custom(GameInput::Idle)));
+        }
+        assert!(reconciler.predicted().is_empty());
+
+        reconciler.push_predicted(local(Custom(GameInput::Fire)));
+        assert_eq!(reconciler.predicted().front_tick_id(), Some(TickId(8)));
+        assert!(reconciler.push_authoritative(combined(Custom(GameInput::Idle))));
+        assert_eq!(reconciler.take_first_misprediction(), Some(TickId(8)));
+    }
+}
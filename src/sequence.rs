@@ -0,0 +1,66 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use tick_id::TickId;
+
+/// Sequence arithmetic for [`TickId`] that wraps around at [`crate::TICK_ID_MAX`].
+///
+/// Two tick ids are compared by the shortest distance between them, so the comparisons are valid
+/// as long as the ticks are less than half the tick range (about 2^31 ticks) apart.
+pub trait TickSequence {
+    fn wrapping_add(self, count: u32) -> TickId;
+    fn wrapping_sub(self, count: u32) -> TickId;
+    /// The signed number of ticks from `other` to `self`.
+    fn wrapping_diff(self, other: TickId) -> i64;
+    fn is_before(&self, other: TickId) -> bool;
+    fn is_after(&self, other: TickId) -> bool;
+}
+
+impl TickSequence for TickId {
+    fn wrapping_add(self, count: u32) -> TickId {
+        TickId(self.0.wrapping_add(count))
+    }
+
+    fn wrapping_sub(self, count: u32) -> TickId {
+        TickId(self.0.wrapping_sub(count))
+    }
+
+    fn wrapping_diff(self, other: TickId) -> i64 {
+        self.0.wrapping_sub(other.0) as i32 as i64
+    }
+
+    fn is_before(&self, other: TickId) -> bool {
+        self.wrapping_diff(other) < 0
+    }
+
+    fn is_after(&self, other: TickId) -> bool {
+        self.wrapping_diff(other) > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_boundary() {
+        let last = TickId(u32::MAX);
+        assert_eq!(last.wrapping_add(1), TickId(0));
+        assert_eq!(last.wrapping_add(3), TickId(2));
+        assert_eq!(TickId(1).wrapping_sub(2), last);
+        assert_eq!(TickId(2).wrapping_diff(last), 3);
+        assert_eq!(last.wrapping_diff(TickId(2)), -3);
+        assert!(last.is_before(TickId(0)));
+        assert!(TickId(0).is_after(last));
+        assert!(!TickId(5).is_before(TickId(5)));
+        assert!(!TickId(5).is_after(TickId(5)));
+    }
+
+    #[test]
+    fn ordinary_ticks() {
+        assert_eq!(TickId(1144).wrapping_diff(TickId(12414)), -11270);
+        assert_eq!(TickId(12414).wrapping_diff(TickId(1144)), 11270);
+        assert!(TickId(1144).is_before(TickId(12414)));
+    }
+}
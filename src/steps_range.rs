@@ -0,0 +1,264 @@
+/*----------------------------------------------------------------------------------------------------------
+ *  Copyright (c) Peter Bjorklund. All rights reserved. https://github.com/nimble-rust/steps
+ *  Licensed under the MIT License. See LICENSE in the project root for license information.
+ *--------------------------------------------------------------------------------------------------------*/
+use tick_id::TickId;
+
+use crate::pending_steps::{PendingSteps, SetOutcome};
+use crate::sequence::TickSequence;
+use crate::{read_octets, DecodeError, Deserialize, ParticipantSteps, Serialize, Step, Steps, StepsError};
+
+pub const STEPS_RANGE_MAX_COUNT: usize = u16::MAX as usize;
+pub const STEPS_RANGE_HEADER_OCTET_COUNT: usize = 6;
+
+/// A run of steps for consecutive ticks, starting at `start_tick_id`.
+///
+/// Encoded as the start tick (u32), the number of ticks (u16) and then each step in tick order.
+#[derive(Debug, PartialEq)]
+pub struct StepsRange<S> {
+    pub start_tick_id: TickId,
+    pub steps: Vec<S>,
+}
+
+impl<S> StepsRange<S> {
+    pub fn new(start_tick_id: TickId, steps: Vec<S>) -> Self {
+        Self {
+            start_tick_id,
+            steps,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn end_tick_id(&self) -> TickId {
+        self.start_tick_id.wrapping_add(self.steps.len() as u32)
+    }
+}
+
+/// The start tick and step count that precede the steps of an encoded [`StepsRange`].
+pub(crate) fn steps_range_header(start_tick_id: TickId, count: usize) -> [u8; STEPS_RANGE_HEADER_OCTET_COUNT] {
+    assert!(count <= STEPS_RANGE_MAX_COUNT, "too many steps in range");
+    let mut header = [0; STEPS_RANGE_HEADER_OCTET_COUNT];
+    header[..4].copy_from_slice(&start_tick_id.value().to_be_bytes());
+    header[4..].copy_from_slice(&(count as u16).to_be_bytes());
+    header
+}
+
+impl<S: Serialize> Serialize for StepsRange<S> {
+    fn serialize(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&steps_range_header(self.start_tick_id, self.steps.len()));
+        for step in &self.steps {
+            step.serialize(buffer);
+        }
+    }
+}
+
+impl<S: Deserialize> Deserialize for StepsRange<S> {
+    fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let start_tick_id = TickId(u32::from_be_bytes(read_octets(bytes)?));
+        let count = u16::from_be_bytes(read_octets(&bytes[4..])?);
+        let mut position = STEPS_RANGE_HEADER_OCTET_COUNT;
+        let mut steps = Vec::new();
+        for _ in 0..count {
+            let (step, octet_count) = S::deserialize(&bytes[position..])?;
+            steps.push(step);
+            position += octet_count;
+        }
+        Ok((Self::new(start_tick_id, steps), position))
+    }
+}
+
+impl<T> Steps<T> {
+    /// Borrows at most `max_count` consecutive steps, starting at `start_tick_id` or the front tick,
+    /// whichever is later.
+    pub fn steps_range(&self, start_tick_id: TickId, max_count: usize) -> StepsRange<&ParticipantSteps<T>> {
+        let start_tick_id = match self.front_tick_id() {
+            Some(front_tick_id) if start_tick_id.is_before(front_tick_id) => front_tick_id,
+            _ => start_tick_id,
+        };
+        let offset = self
+            .front_tick_id()
+            .map_or(0, |front_tick_id| start_tick_id.wrapping_diff(front_tick_id) as usize);
+        let steps = self
+            .steps
+            .iter()
+            .skip(offset)
+            .take(max_count.min(STEPS_RANGE_MAX_COUNT))
+            .map(|info| &info.step)
+            .collect();
+
+        StepsRange::new(start_tick_id, steps)
+    }
+
+    /// Appends the ticks in `range` that follow the last pushed tick. Ticks that were already pushed are skipped,
+    /// but must match the stored steps if they are still queued.
+    /// Returns the number of appended ticks.
+    pub fn push_range(&mut self, range: StepsRange<ParticipantSteps<T>>) -> Result<usize, StepsError>
+    where
+        T: PartialEq,
+    {
+        if range.start_tick_id.is_after(self.expected_write_id) {
+            return Err(StepsError::TickTooFarAhead {
+                tick_id: range.start_tick_id,
+                max_tick_id: self.expected_write_id,
+            });
+        }
+
+        let already_pushed = self.expected_write_id.wrapping_diff(range.start_tick_id) as usize;
+        for (offset, step) in range.steps.iter().enumerate().take(already_pushed) {
+            let tick_id = range.start_tick_id.wrapping_add(offset as u32);
+            if let Some(stored) = self.get(tick_id) {
+                if stored.step != *step {
+                    return Err(StepsError::ConflictingDuplicate(tick_id));
+                }
+            }
+        }
+
+        let count_before = self.steps.len();
+        for step in range.steps.into_iter().skip(already_pushed) {
+            self.push(step);
+        }
+
+        Ok(self.steps.len() - count_before)
+    }
+}
+
+impl<T> PendingSteps<T> {
+    /// Sets every tick in `range` that fits in the window. Ticks that are older than the window or already set
+    /// are skipped, and ticks beyond the window are dropped, since resends commonly run past the window.
+    /// Returns the number of ticks that were set.
+    pub fn set_range(&mut self, range: StepsRange<Step<T>>) -> usize {
+        let mut set_count = 0;
+        for (offset, step) in range.steps.into_iter().enumerate() {
+            let tick_id = range.start_tick_id.wrapping_add(offset as u32);
+            match self.set(tick_id, step) {
+                Ok(SetOutcome::Accepted) => set_count += 1,
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+
+        set_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ParticipantId;
+    use crate::Step::Custom;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    struct Jump(u8);
+
+    impl Serialize for Jump {
+        fn serialize(&self, buffer: &mut Vec<u8>) {
+            buffer.push(self.0);
+        }
+    }
+
+    impl Deserialize for Jump {
+        fn deserialize(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+            let [height] = read_octets(bytes)?;
+            Ok((Jump(height), 1))
+        }
+    }
+
+    fn participant_steps(height: u8) -> ParticipantSteps<Jump> {
+        let mut participant_steps = ParticipantSteps::new();
+        participant_steps.push(ParticipantId(1), Custom(Jump(height))).unwrap();
+        participant_steps
+    }
+
+    #[test]
+    fn serialize_and_deserialize_range() {
+        let mut steps = Steps::new_with_initial_tick(TickId(100));
+        for height in 0..5 {
+            steps.push(participant_steps(height));
+        }
+
+        let mut buffer = Vec::new();
+        steps.steps_range(TickId(101), 3).serialize(&mut buffer);
+        assert_eq!(&buffer[..6], &[0, 0, 0, 101, 0, 3]);
+
+        let (range, octet_count) = StepsRange::<ParticipantSteps<Jump>>::deserialize(&buffer).unwrap();
+        assert_eq!(octet_count, buffer.len());
+        assert_eq!(range.start_tick_id, TickId(101));
+        assert_eq!(range.end_tick_id(), TickId(104));
+        assert_eq!(range.steps, vec![participant_steps(1), participant_steps(2), participant_steps(3)]);
+    }
+
+    #[test]
+    fn range_is_clamped_to_front() {
+        let mut steps = Steps::new_with_initial_tick(TickId(10));
+        steps.push(participant_steps(7));
+        let range = steps.steps_range(TickId(2), 10);
+        assert_eq!(range.start_tick_id, TickId(10));
+        assert_eq!(range.len(), 1);
+    }
+
+    #[test]
+    fn push_overlapping_range() {
+        let mut steps = Steps::new_with_initial_tick(TickId(20));
+        steps.push(participant_steps(0));
+        steps.push(participant_steps(1));
+
+        let range = StepsRange::new(TickId(21), vec![participant_steps(1), participant_steps(2), participant_steps(3)]);
+        assert_eq!(steps.push_range(range), Ok(2));
+        assert_eq!(steps.len(), 4);
+        assert_eq!(steps.back_tick_id(), Some(TickId(23)));
+    }
+
+    #[test]
+    fn push_range_with_gap() {
+        let mut steps = Steps::new_with_initial_tick(TickId(20));
+        let range = StepsRange::new(TickId(22), vec![participant_steps(2)]);
+        assert_eq!(
+            steps.push_range(range),
+            Err(StepsError::TickTooFarAhead {
+                tick_id: TickId(22),
+                max_tick_id: TickId(20)
+            })
+        );
+        assert!(steps.is_empty());
+    }
+
+    #[test]
+    fn push_conflicting_range() {
+        let mut steps = Steps::new_with_initial_tick(TickId(20));
+        steps.push(participant_steps(0));
+        steps.push(participant_steps(1));
+
+        let range = StepsRange::new(TickId(20), vec![participant_steps(0), participant_steps(9), participant_steps(2)]);
+        assert_eq!(steps.push_range(range), Err(StepsError::ConflictingDuplicate(TickId(21))));
+        assert_eq!(steps.len(), 2);
+    }
+
+    #[test]
+    fn set_range_in_pending_steps() {
+        let mut pending_steps = PendingSteps::new(8, TickId(50));
+        let range = StepsRange::new(TickId(48), vec![Custom(Jump(0)), Custom(Jump(1)), Custom(Jump(2)), Step::Forced]);
+
+        let mut buffer = Vec::new();
+        range.serialize(&mut buffer);
+        let (decoded, _) = StepsRange::<Step<Jump>>::deserialize(&buffer).unwrap();
+
+        assert_eq!(pending_steps.set_range(decoded), 2);
+        assert_eq!(pending_steps.front_tick_id(), Some(TickId(50)));
+    }
+
+    #[test]
+    fn set_range_stops_at_window_edge() {
+        let mut pending_steps = PendingSteps::new(4, TickId(0));
+        let range = StepsRange::new(TickId(0), (0..6).map(|height| Custom(Jump(height))).collect());
+        assert_eq!(pending_steps.set_range(range), 4);
+        assert_eq!(pending_steps.drain_ready().count(), 4);
+    }
+}